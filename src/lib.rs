@@ -40,11 +40,26 @@ pub mod util;
 pub mod suspend;
 pub mod global;
 pub mod memkv;
+#[cfg(feature = "sim")]
+pub mod sim;
 
 
 use uuid::Uuid;
+use std::time::Duration;
 pub use crate::process::DispatchError;
 
+/// Start the default node, [Node::system](crate::node::Node::system), configured by `config`.
+///
+/// The configuration is registered before the node controller, process registry and key-value store start,
+/// so none of them can observe the default one. Use [NodeConfig::load](crate::node::NodeConfig::load) to read it
+/// from a config file and the environment.
+///
+/// Panics if the configuration of the default node was already accessed
+pub fn start(config: node::NodeConfig) -> node::Node {
+    actix::SystemRegistry::set(global::Global(config).start());
+    node::Node::system()
+}
+
 
 #[derive(Debug, Clone)]
 pub struct Broadcast {
@@ -103,11 +118,14 @@ where M: Message<Result=Result<R, DispatchError>> + 'static,
 /// link sent the message over the wire
 ///
 /// Otherwise sets up a correlation counter and waits for response with a timeout(to prevent DOS attacks on correlation cache)
+///
+/// The timeout is either the one specified by the caller, or [NodeConfig::request_timeout](crate::node::NodeConfig::request_timeout)
 #[derive(Debug, Clone)]
 pub struct MethodCall {
     pub(crate) procid: Option<Uuid>,
     pub(crate) method: u32,
     pub(crate) body: Bytes,
+    pub(crate) timeout: Option<Duration>,
 }
 
 impl Message for MethodCall {
@@ -2,7 +2,7 @@ use crate::import::*;
 use crate::proto::{Get, Value, Key};
 use crate::{Process, NodeDispatch};
 use crate::process::DispatchError;
-use crate::node::{NodeController, RegisterGlobalHandler, FromNode, NodeId, ListNodes};
+use crate::node::{Node, RegisterGlobalHandler, FromNode, NodeId, ListNodes};
 use crate::util::RpcMethod;
 use futures::TryStreamExt;
 use futures::stream::FuturesUnordered;
@@ -31,6 +31,8 @@ impl Message for RemoteRead {
 
 pub struct GlobalFind {
     pub key: Vec<u8>,
+    /// Only ask nodes with this role
+    pub role: Option<String>,
 }
 
 impl Message for GlobalFind {
@@ -58,7 +60,10 @@ impl Handler<RemoteRead> for MemKv {
     type Result = ResponseFuture<Result<Option<Vec<u8>>, DispatchError>>;
 
     fn handle(&mut self, msg: RemoteRead, ctx: &mut Context<Self>) -> Self::Result {
-        Box::pin(NodeId(msg.node).send(Get(Key { data: msg.key })).map_ok(|v| v.data))
+        let req = async move {
+            NodeId(msg.node).send(Get(Key { data: msg.key })).await.map(|v| v.data)
+        };
+        Box::pin(self.node.scope(req))
     }
 }
 
@@ -66,36 +71,52 @@ impl Handler<GlobalFind> for MemKv {
     type Result = ResponseFuture<Result<Option<Vec<u8>>, DispatchError>>;
 
     fn handle(&mut self, msg: GlobalFind, ctx: &mut Context<Self>) -> Self::Result {
-        let nc = NodeController::from_registry();
-        let nodes = nc.send(ListNodes);
-
-        let uuids = nodes.map(|r| r.unwrap());
-        let res = async move {
-            let mut tasks: FuturesUnordered<_> = uuids.await.into_iter().map(|n| {
-                NodeId(n).send(Get(Key { data: msg.key.clone() })).map_ok(|v| v.data)
+        let node = self.node.clone();
+        let GlobalFind { key, role } = msg;
+        let nodes = node.controller().send(ListNodes { role });
+
+        let nodes = nodes.map(|r| r.unwrap());
+        let res = node.scope(async move {
+            let mut tasks: FuturesUnordered<_> = nodes.await.into_iter().map(|n| {
+                NodeId(n.id).send(Get(Key { data: key.clone() })).map_ok(|v| v.data)
             }).collect();
             // We have received A response ( one future resolved, which had an OK result, and the result vas an actual value)
             while let Some(Ok(Some(data))) = tasks.next().await {
                 return Ok(Some(data));
             }
             return Ok(None);
-        };
+        });
         Box::pin(res)
     }
 }
 
 /// Simple in-memory key-value store.
 /// Local instance can be modified, remote instances can be only read
-#[derive(Default)]
 pub struct MemKv {
+    node: Node,
     data: BTreeMap<Vec<u8>, Vec<u8>>
 }
 
+impl Default for MemKv {
+    fn default() -> Self {
+        MemKv::new(Node::system())
+    }
+}
+
+impl MemKv {
+    pub(crate) fn new(node: Node) -> Self {
+        MemKv {
+            node,
+            data: BTreeMap::new(),
+        }
+    }
+}
+
 impl Actor for MemKv {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        NodeController::from_registry().do_send(RegisterGlobalHandler::new::<Get, _>(ctx.address().recipient()));
+        self.node.controller().do_send(RegisterGlobalHandler::new::<Get, _>(ctx.address().recipient()));
     }
 }
 
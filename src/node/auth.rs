@@ -0,0 +1,66 @@
+use crate::import::*;
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use sha2::Sha256;
+
+/// Length of the challenge nonce sent in [Meta](crate::proto::Meta)
+const NONCE_LEN: usize = 32;
+
+pub(crate) fn nonce() -> Vec<u8> {
+    let mut nonce = vec![0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Side of the handshake a digest was computed by.
+///
+/// Digests of the two sides differ, so the answer of one can't be reflected back as the answer of the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Role {
+    Dialer = 1,
+    Acceptor = 2,
+}
+
+impl Role {
+    pub(crate) fn peer(self) -> Role {
+        match self {
+            Role::Dialer => Role::Acceptor,
+            Role::Acceptor => Role::Dialer,
+        }
+    }
+}
+
+/// Identities and challenges exchanged in [Meta](crate::proto::Meta), which both digests cover.
+///
+/// Each digest is only valid for the connection both nonces were sent on
+pub(crate) struct Transcript<'a> {
+    pub dialer: Uuid,
+    pub acceptor: Uuid,
+    pub dialer_nonce: &'a [u8],
+    pub acceptor_nonce: &'a [u8],
+}
+
+fn mac(cookie: &str, role: Role, t: &Transcript) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_varkey(cookie.as_bytes()).unwrap();
+    mac.update(&[role as u8]);
+    mac.update(t.dialer.as_bytes());
+    mac.update(t.acceptor.as_bytes());
+    // Nonces are chosen by the nodes, length prefix keeps their boundary unambiguous
+    for nonce in &[t.dialer_nonce, t.acceptor_nonce] {
+        mac.update(&(nonce.len() as u32).to_be_bytes());
+        mac.update(nonce);
+    }
+    mac
+}
+
+/// Prove to the remote node we know the cookie
+pub(crate) fn digest(cookie: &str, role: Role, t: &Transcript) -> Vec<u8> {
+    mac(cookie, role, t).finalize().into_bytes().to_vec()
+}
+
+/// Check the digest sent by the remote node, which has the given role
+pub(crate) fn verify(cookie: &str, role: Role, t: &Transcript, digest: &[u8]) -> bool {
+    mac(cookie, role, t).verify(digest).is_ok()
+}
@@ -0,0 +1,61 @@
+use std::ops::{BitAnd, BitOr};
+
+/// Newest version of the wire protocol this node speaks
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest version of the wire protocol this node still speaks.
+///
+/// Linked nodes use the newest version both of them speak, so nodes of a cluster can be upgraded one at a time.
+/// Nodes refuse to link with peers which have no version in common
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Newest protocol version spoken both by this node, and a node speaking versions `min..=max`
+pub fn negotiate(min: u32, max: u32) -> Option<u32> {
+    let version = max.min(PROTOCOL_VERSION);
+    if version >= min.max(MIN_PROTOCOL_VERSION) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+/// Version of quix running on this node
+pub const QUIX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Set of optional protocol features, exchanged during handshake.
+///
+/// A feature is used on a link only if both nodes support it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(pub u64);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    /// Link is encrypted with TLS
+    pub const TLS: Capabilities = Capabilities(1 << 0);
+    /// Node requires cookie authentication
+    pub const AUTH: Capabilities = Capabilities(1 << 1);
+    /// Node accepts zstd compressed frames
+    pub const COMPRESSION: Capabilities = Capabilities(1 << 2);
+    /// Node reassembles large frames sent in chunks
+    pub const CHUNKING: Capabilities = Capabilities(1 << 3);
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Capabilities;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Capabilities(self.0 & rhs.0)
+    }
+}
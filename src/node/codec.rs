@@ -0,0 +1,142 @@
+use crate::import::*;
+
+use std::io;
+use std::io::Read;
+use crate::proto::Net;
+
+/// Set in the length header of frames, whose body is compressed with zstd
+const COMPRESSED: u32 = 1 << 31;
+
+/// Default limit on the size of a single frame, see [NodeConfig::max_frame_size](crate::node::NodeConfig::max_frame_size)
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Compression of large frames. Used on links, where both nodes enable it
+#[derive(Debug, Clone)]
+pub struct CompressionConfig {
+    /// Frames with smaller body are sent uncompressed
+    pub threshold: usize,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            threshold: 16 * 1024,
+            level: 3,
+        }
+    }
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where E: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Frames are prost encoded [Net] messages, prefixed with u32 length.
+///
+/// Highest bit of the length marks compressed frames.
+/// Incoming frames larger than `max_frame_size`, before or after decompression, are rejected
+#[derive(Debug, Clone)]
+pub struct NetCodec {
+    compression: Option<CompressionConfig>,
+    max_frame_size: usize,
+}
+
+impl Default for NetCodec {
+    fn default() -> Self {
+        NetCodec {
+            compression: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl NetCodec {
+    /// Codec which compresses outgoing frames larger than configured threshold
+    pub fn compressed(cfg: CompressionConfig) -> Self {
+        NetCodec {
+            compression: Some(cfg),
+            ..Default::default()
+        }
+    }
+
+    /// Set the limit on size of incoming frames
+    pub fn max_frame_size(mut self, max: usize) -> Self {
+        self.max_frame_size = max;
+        self
+    }
+}
+
+impl tokio_util::codec::Encoder<Net> for NetCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Net, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let len = prost::Message::encoded_len(&item);
+
+        if let Some(ref cfg) = self.compression {
+            if len >= cfg.threshold {
+                let mut buf = Vec::with_capacity(len);
+                prost::Message::encode(&item, &mut buf).map_err(invalid_data)?;
+                let compressed = zstd::stream::encode_all(&buf[..], cfg.level)?;
+
+                // Incompressible data is sent as is
+                if compressed.len() < len {
+                    dst.reserve(4 + compressed.len());
+                    dst.put_u32(compressed.len() as u32 | COMPRESSED);
+                    dst.extend_from_slice(&compressed);
+                    return Ok(());
+                }
+            }
+        }
+
+        dst.reserve(4 + len);
+        dst.put_u32(len as _);
+
+        prost::Message::encode(&item, dst)
+            .map_err(invalid_data)
+    }
+}
+
+impl tokio_util::codec::Decoder for NetCodec {
+    type Item = Net;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let header = u32::from_be_bytes([src[0], src[1], src[2], src[3]]);
+        let len = (header & !COMPRESSED) as usize;
+        // Check before buffering, the header alone must not make us wait for gigabytes
+        if len > self.max_frame_size {
+            return Err(invalid_data(format!("Frame of {} bytes exceeds limit of {}", len, self.max_frame_size)));
+        }
+        if src.len() < len + 4 {
+            return Ok(None);
+        }
+        src.advance(4);
+        let msg = src.split_to(len);
+
+        if header & COMPRESSED != 0 {
+            // Read at most one byte over the limit, so a small frame can't expand into huge allocation
+            let mut out = Vec::new();
+            zstd::stream::read::Decoder::new(&msg[..])?
+                .take(self.max_frame_size as u64 + 1)
+                .read_to_end(&mut out)
+                .map_err(invalid_data)?;
+            if out.len() > self.max_frame_size {
+                return Err(invalid_data(format!("Decompressed frame exceeds limit of {}", self.max_frame_size)));
+            }
+            let msg = out;
+            prost::Message::decode(&msg[..])
+                .map_err(invalid_data)
+                .map(Some)
+        } else {
+            prost::Message::decode(msg)
+                .map_err(invalid_data)
+                .map(Some)
+        }
+    }
+}
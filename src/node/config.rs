@@ -0,0 +1,248 @@
+use crate::import::*;
+
+use std::io;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::Deserialize;
+use crate::node::{NodeConfig, TlsConfig};
+use crate::node::transport::NodeAddr;
+use crate::node::seeds::SEEDS_ENV;
+
+/// Environment variable with path of the config file read by [NodeConfig::load]
+pub const CONFIG_ENV: &str = "QUIX_CONFIG";
+
+/// Prefix of environment variables read by [NodeConfig::from_env]
+const ENV_PREFIX: &str = "QUIX_";
+
+/// Error loading [NodeConfig] from a file or the environment
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Config file could not be read
+    Io(PathBuf, io::Error),
+    /// Config file is not valid TOML or YAML, or contains unknown settings
+    Parse(PathBuf, String),
+    /// Value of a setting is invalid
+    Invalid {
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Reading config file {:?} failed: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "Invalid config file {:?}: {}", path, e),
+            ConfigError::Invalid { key, message } => write!(f, "Invalid value of {}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: &str, message: impl ToString) -> ConfigError {
+    ConfigError::Invalid { key: key.to_string(), message: message.to_string() }
+}
+
+/// Settings of a node as written in a config file, all of them optional.
+///
+/// Durations are given in milliseconds
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    id: Option<String>,
+    name: Option<String>,
+    listen: Option<Vec<String>>,
+    advertise: Option<String>,
+    seeds: Option<Vec<String>>,
+    seeds_file: Option<PathBuf>,
+    seeds_from_env: Option<bool>,
+    cookie: Option<String>,
+    request_timeout_ms: Option<u64>,
+    heartbeat_interval_ms: Option<u64>,
+    heartbeat_timeout_ms: Option<u64>,
+    handshake_timeout_ms: Option<u64>,
+    tls: Option<TlsSettings>,
+    roles: Option<Vec<String>>,
+    metadata: Option<HashMap<String, String>>,
+    hidden: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSettings {
+    cert: PathBuf,
+    key: PathBuf,
+    ca: PathBuf,
+    #[serde(default)]
+    verify_node_id: bool,
+}
+
+fn parse_addr(key: &str, addr: &str) -> Result<NodeAddr, ConfigError> {
+    addr.parse().map_err(|e| invalid(key, e))
+}
+
+fn parse_addrs(key: &str, addrs: &[String]) -> Result<Vec<NodeAddr>, ConfigError> {
+    addrs.iter().map(|addr| parse_addr(key, addr)).collect()
+}
+
+/// Value of `QUIX_<key>`, if set
+fn env(key: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, key)).ok()
+}
+
+fn env_parse<T: FromStr>(key: &str) -> Result<Option<T>, ConfigError>
+where T::Err: fmt::Display
+{
+    env(key)
+        .map(|v| v.trim().parse().map_err(|e| invalid(&format!("{}{}", ENV_PREFIX, key), e)))
+        .transpose()
+}
+
+/// Comma or whitespace separated list in `QUIX_<key>`
+fn env_list(key: &str) -> Option<Vec<String>> {
+    env(key).map(|v| {
+        v.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+impl Settings {
+    fn from_file(path: &Path) -> Result<Settings, ConfigError> {
+        let data = std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let parse_err = |e: &dyn fmt::Display| ConfigError::Parse(path.to_owned(), e.to_string());
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&data).map_err(|e| parse_err(&e)),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&data).map_err(|e| parse_err(&e)),
+            _ => Err(parse_err(&"Unknown format, expected .toml, .yaml or .yml file")),
+        }
+    }
+
+    fn from_env() -> Result<Settings, ConfigError> {
+        let tls = match (env("TLS_CERT"), env("TLS_KEY"), env("TLS_CA")) {
+            (None, None, None) => None,
+            (Some(cert), Some(key), Some(ca)) => Some(TlsSettings {
+                cert: cert.into(),
+                key: key.into(),
+                ca: ca.into(),
+                verify_node_id: env_parse("TLS_VERIFY_NODE_ID")?.unwrap_or(false),
+            }),
+            _ => return Err(invalid("QUIX_TLS_CERT", "QUIX_TLS_CERT, QUIX_TLS_KEY and QUIX_TLS_CA must be set together")),
+        };
+        Ok(Settings {
+            id: env("ID"),
+            name: env("NAME"),
+            listen: env_list("LISTEN"),
+            advertise: env("ADVERTISE"),
+            // Seeds are read from the same variable when joining the cluster, see [SEEDS_ENV]
+            seeds: None,
+            seeds_file: env("SEEDS_FILE").map(PathBuf::from),
+            // Setting the seeds variable alone is enough to join through them
+            seeds_from_env: env_parse("SEEDS_FROM_ENV")?.or_else(|| std::env::var_os(SEEDS_ENV).map(|_| true)),
+            cookie: env("COOKIE"),
+            request_timeout_ms: env_parse("REQUEST_TIMEOUT_MS")?,
+            heartbeat_interval_ms: env_parse("HEARTBEAT_INTERVAL_MS")?,
+            heartbeat_timeout_ms: env_parse("HEARTBEAT_TIMEOUT_MS")?,
+            handshake_timeout_ms: env_parse("HANDSHAKE_TIMEOUT_MS")?,
+            tls,
+            roles: env_list("ROLES"),
+            metadata: None,
+            hidden: env_parse("HIDDEN")?,
+        })
+    }
+
+    /// Override values of `config` with settings which are present
+    fn apply(self, mut config: NodeConfig) -> Result<NodeConfig, ConfigError> {
+        if let Some(id) = self.id {
+            config.id = Uuid::parse_str(&id).map_err(|e| invalid("id", e))?;
+        }
+        if let Some(name) = self.name {
+            config.name = Some(name);
+        }
+        if let Some(listen) = self.listen {
+            config.listen = parse_addrs("listen", &listen)?;
+        }
+        if let Some(advertise) = self.advertise {
+            config.advertise = Some(parse_addr("advertise", &advertise)?);
+        }
+        if let Some(seeds) = self.seeds {
+            config.seeds = parse_addrs("seeds", &seeds)?;
+        }
+        if let Some(seeds_file) = self.seeds_file {
+            config.seeds_file = Some(seeds_file);
+        }
+        if let Some(seeds_from_env) = self.seeds_from_env {
+            config.seeds_from_env = seeds_from_env;
+        }
+        if let Some(cookie) = self.cookie {
+            config.cookie = Some(cookie);
+        }
+        if let Some(ms) = self.request_timeout_ms {
+            config.request_timeout = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.heartbeat_interval_ms {
+            config.heartbeat_interval = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.heartbeat_timeout_ms {
+            config.heartbeat_timeout = Duration::from_millis(ms);
+        }
+        if let Some(ms) = self.handshake_timeout_ms {
+            config.handshake_timeout = Duration::from_millis(ms);
+        }
+        if let Some(tls) = self.tls {
+            config.tls = Some(TlsConfig {
+                cert: tls.cert,
+                key: tls.key,
+                ca: tls.ca,
+                verify_node_id: tls.verify_node_id,
+            });
+        }
+        if let Some(roles) = self.roles {
+            config.roles = roles;
+        }
+        if let Some(metadata) = self.metadata {
+            config.metadata = metadata;
+        }
+        if let Some(hidden) = self.hidden {
+            config.hidden = hidden;
+        }
+        Ok(config)
+    }
+}
+
+impl NodeConfig {
+    /// Read configuration from a `.toml`, `.yaml` or `.yml` file. Settings missing from the file keep their defaults.
+    ///
+    /// Recognized settings are `id`, `name`, `listen`, `advertise`, `seeds`, `seeds_file`, `seeds_from_env`,
+    /// `cookie`, `request_timeout_ms`, `heartbeat_interval_ms`, `heartbeat_timeout_ms`, `handshake_timeout_ms`, `roles`,
+    /// `metadata`, `hidden`
+    /// and a `tls` table with `cert`, `key`, `ca` and `verify_node_id`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<NodeConfig, ConfigError> {
+        Settings::from_file(path.as_ref())?.apply(NodeConfig::default())
+    }
+
+    /// Read configuration from `QUIX_*` environment variables, the upper case names of settings read by [NodeConfig::from_file].
+    ///
+    /// Lists like `QUIX_LISTEN` and `QUIX_ROLES` are separated by commas or whitespace, TLS is configured by
+    /// `QUIX_TLS_CERT`, `QUIX_TLS_KEY`, `QUIX_TLS_CA` and `QUIX_TLS_VERIFY_NODE_ID`. Seeds are read from [SEEDS_ENV](crate::node::SEEDS_ENV)
+    /// when it is set, unless `QUIX_SEEDS_FROM_ENV` is `false`
+    pub fn from_env() -> Result<NodeConfig, ConfigError> {
+        Self::default().with_env()
+    }
+
+    /// Read the config file named by [CONFIG_ENV] if it is set, and override its settings from the environment
+    pub fn load() -> Result<NodeConfig, ConfigError> {
+        let config = match std::env::var_os(CONFIG_ENV) {
+            Some(path) => Self::from_file(PathBuf::from(path))?,
+            None => Self::default(),
+        };
+        config.with_env()
+    }
+
+    fn with_env(self) -> Result<NodeConfig, ConfigError> {
+        Settings::from_env()?.apply(self)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::import::*;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::net::udp::{RecvHalf, SendHalf};
+use crate::node::NodeController;
+use crate::node::transport::NodeAddr;
+use crate::proto::Announce;
+use crate::util::uuid;
+
+/// Largest announcement we accept
+const MAX_ANNOUNCE: usize = 1024;
+
+/// Longest pause after repeated failures to receive from the socket
+const MAX_RECEIVE_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Finding nodes on the local network through UDP multicast.
+///
+/// Each node periodically announces its id and listen address to the multicast group,
+/// and connects to nodes announcing the same cluster name
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// Name of the cluster, announcements of other clusters are ignored
+    pub cluster: String,
+    /// Multicast group and port the announcements are sent to
+    pub group: SocketAddrV4,
+    /// Local interface used to send and receive announcements, chosen by the OS if unspecified
+    pub interface: Ipv4Addr,
+    /// Interval between announcements of this node
+    pub interval: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            cluster: "quix".to_string(),
+            group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), 9089),
+            interface: Ipv4Addr::UNSPECIFIED,
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Node found through a multicast announcement
+pub(crate) struct Discovered {
+    id: Uuid,
+    addr: NodeAddr,
+}
+
+/// Open socket joined to the multicast group. All nodes on the host share the port
+fn bind(cfg: &DiscoveryConfig) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, cfg.group.port())).into())?;
+    socket.join_multicast_v4(cfg.group.ip(), &cfg.interface)?;
+    socket.set_multicast_if_v4(&cfg.interface)?;
+    // Nodes running on this host need to hear us as well
+    socket.set_multicast_loop_v4(true)?;
+    UdpSocket::from_std(socket.into_udp_socket())
+}
+
+async fn announce(mut tx: SendHalf, group: SocketAddr, data: Vec<u8>, interval: Duration) {
+    loop {
+        if let Err(e) = tx.send_to(&data, &group).await {
+            log::warn!("Sending node announcement to {} failed: {}", group, e);
+        }
+        tokio::time::delay_for(interval).await;
+    }
+}
+
+/// Announcements of other nodes in `cluster`
+fn receive(rx: RecvHalf, cluster: String) -> impl Stream<Item=Discovered> {
+    futures::stream::unfold(rx, move |mut rx| {
+        let cluster = cluster.clone();
+        async move {
+            let mut buf = [0u8; MAX_ANNOUNCE];
+            let mut backoff = Duration::from_millis(10);
+            loop {
+                let (len, from) = match rx.recv_from(&mut buf).await {
+                    Ok(res) => res,
+                    Err(e) => {
+                        // Errors of the socket itself would be returned right away again,
+                        // don't spin on them
+                        log::warn!("Receiving node announcement failed: {}", e);
+                        tokio::time::delay_for(backoff).await;
+                        backoff = std::cmp::min(backoff * 2, MAX_RECEIVE_BACKOFF);
+                        continue;
+                    }
+                };
+                backoff = Duration::from_millis(10);
+                let msg = match <Announce as prost::Message>::decode(&buf[..len]) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        log::debug!("Invalid node announcement from {}: {}", from, e);
+                        continue;
+                    }
+                };
+                if msg.cluster != cluster || msg.nodeid.len() != 16 {
+                    continue;
+                }
+                if let Ok(addr) = msg.addr.parse::<NodeAddr>() {
+                    let id = uuid(msg.nodeid.as_slice());
+                    let addr = addr.seen_at(&NodeAddr::Tcp(from));
+                    return Some((Discovered { id, addr }, rx));
+                }
+            }
+        }
+    })
+}
+
+impl NodeController {
+    /// Announce this node on the local network, and connect to nodes announcing themselves
+    pub(super) fn start_discovery(&mut self, ctx: &mut Context<Self>) {
+        let cfg = match self.config.discovery {
+            Some(ref cfg) if !self.config.hidden => cfg.clone(),
+            _ => return,
+        };
+        let addr = match self.config.advertised() {
+            Some(addr) => addr.to_string(),
+            None => {
+                log::warn!("Node discovery needs a listen or advertised address");
+                return;
+            }
+        };
+        let socket = match bind(&cfg) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::error!("Starting node discovery on {} failed: {}", cfg.group, e);
+                return;
+            }
+        };
+        log::info!("Discovering nodes of cluster {:?} on {}", cfg.cluster, cfg.group);
+
+        let msg = Announce {
+            cluster: cfg.cluster.clone(),
+            nodeid: self.config.id.as_bytes().to_vec(),
+            addr,
+        };
+        let mut data = Vec::new();
+        prost::Message::encode(&msg, &mut data).unwrap();
+
+        let (rx, tx) = socket.split();
+        let announce = ctx.spawn(wrap_future(announce(tx, cfg.group.into(), data, cfg.interval)));
+        let receive = ctx.add_stream(receive(rx, cfg.cluster));
+        self.background.push(announce);
+        self.background.push(receive);
+    }
+}
+
+impl StreamHandler<Discovered> for NodeController {
+    fn handle(&mut self, item: Discovered, ctx: &mut Context<Self>) {
+        self.discovered(item.id, item.addr, ctx);
+    }
+}
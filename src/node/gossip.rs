@@ -0,0 +1,110 @@
+use crate::import::*;
+
+use crate::node::{NodeController, FromNode};
+use crate::node::link::{NodeLink, Peer};
+use crate::node::transport::NodeAddr;
+use crate::node::reconnect;
+use crate::proto::{self, Peers};
+use crate::util::uuid;
+
+/// Listen addresses of nodes in the cluster, exchanged between linked nodes to form a full mesh
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeerList(pub Vec<(Uuid, NodeAddr)>);
+
+impl Message for PeerList {
+    type Result = ();
+}
+
+impl From<&PeerList> for Peers {
+    fn from(list: &PeerList) -> Self {
+        Peers {
+            peers: list.0.iter().map(|(id, addr)| proto::Peer {
+                nodeid: id.as_bytes().to_vec(),
+                addr: addr.to_string(),
+            }).collect(),
+        }
+    }
+}
+
+impl From<Peers> for PeerList {
+    fn from(peers: Peers) -> Self {
+        // Entries we can't understand are skipped, the rest of the list is still useful
+        PeerList(peers.peers.into_iter()
+            .filter(|p| p.nodeid.len() == 16)
+            .filter_map(|p| Some((uuid(p.nodeid.as_slice()), p.addr.parse().ok()?)))
+            .collect())
+    }
+}
+
+impl NodeController {
+    /// Tell newly linked node about the nodes we know, and announce it to all of them
+    pub(super) fn announce(&mut self, peer: &Peer, link: &Addr<NodeLink>) {
+        if self.config.hidden || peer.hidden {
+            return;
+        }
+
+        let known = PeerList(self.peers.values()
+            .filter(|p| p.id != peer.id && !p.hidden)
+            .filter_map(|p| Some((p.id, p.listen.clone()?)))
+            .collect());
+        if !known.0.is_empty() {
+            link.do_send(known);
+        }
+
+        if let Some(ref listen) = peer.listen {
+            let announce = PeerList(vec![(peer.id, listen.clone())]);
+            for p in self.peers.values().filter(|p| p.id != peer.id && !p.hidden) {
+                if let Some(link) = self.links.get(&p.id) {
+                    link.do_send(announce.clone());
+                }
+            }
+        }
+    }
+
+    /// Connect to a node we learned about, unless we are linked to it already
+    pub(super) fn discovered(&mut self, id: Uuid, addr: NodeAddr, ctx: &mut Context<Self>) {
+        if self.config.hidden || self.shutting_down {
+            return;
+        }
+        if id == self.config.id || self.links.contains_key(&id) || self.connecting.contains_key(&id) {
+            return;
+        }
+        // Both nodes learn about each other, only the one with lower id dials,
+        // so they don't race to create two links
+        if id < self.config.id {
+            return;
+        }
+        self.join(id, addr, ctx);
+    }
+
+    fn join(&mut self, id: Uuid, addr: NodeAddr, ctx: &mut Context<Self>) {
+        log::info!("Discovered node {} at {}", id, addr);
+        let conn = reconnect::connect(self.node.clone(), self.config.clone(), self.tls.clone(), addr.clone(), None);
+        let fut = wrap_future(conn).map(move |res, this: &mut Self, ctx| {
+            this.connecting.remove(&id);
+            match res {
+                Ok((peer, link)) => {
+                    if peer.id != id {
+                        log::warn!("Node at {} announced as {}, but is {}", addr, id, peer.id);
+                    }
+                    if let Err(e) = this.dialed_up(peer, link, addr, ctx) {
+                        log::warn!("Connecting to discovered node {} failed: {}", id, e);
+                    }
+                }
+                Err(e) => log::warn!("Connecting to discovered node {} failed: {}", id, e),
+            }
+        });
+        let handle = ctx.spawn(fut);
+        self.connecting.insert(id, handle);
+    }
+}
+
+impl Handler<FromNode<PeerList>> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: FromNode<PeerList>, ctx: &mut Context<Self>) {
+        for (id, addr) in msg.inner.0 {
+            self.discovered(id, addr, ctx);
+        }
+    }
+}
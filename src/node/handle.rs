@@ -0,0 +1,99 @@
+use crate::import::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::node::{NodeController, NodeConfig};
+use crate::process::registry::ProcessRegistry;
+use crate::memkv::MemKv;
+
+/// Context of an actor which is not started yet, but already has an address
+fn context<A: Actor<Context=Context<A>>>() -> Context<A> {
+    let (_, rx) = actix::dev::channel::channel(16);
+    Context::with_receiver(rx)
+}
+
+tokio::task_local! {
+    static CURRENT: Node;
+}
+
+/// Number of nodes started with [Node::start] in this process
+static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle to a quix node, made of its controller, process registry and key-value store.
+///
+/// Several nodes can run inside one actix system, each started with [Node::start].
+/// The singleton services form the default node, see [Node::system]
+#[derive(Clone)]
+pub struct Node {
+    controller: Addr<NodeController>,
+    registry: Addr<ProcessRegistry>,
+    memkv: Addr<MemKv>,
+}
+
+impl Node {
+    /// Start a new node with its own set of actors, configured by `config`
+    pub fn start(config: NodeConfig) -> Node {
+        let controller = context();
+        let registry = context();
+        let memkv = context();
+
+        // Actors of the node need to know each other, so the addresses are created upfront
+        let node = Node {
+            controller: controller.address(),
+            registry: registry.address(),
+            memkv: memkv.address(),
+        };
+
+        controller.run(NodeController::new(node.clone(), config));
+        registry.run(ProcessRegistry::new(node.clone()));
+        memkv.run(MemKv::new(node.clone()));
+        STARTED.fetch_add(1, Ordering::Relaxed);
+        node
+    }
+
+    /// Default node, made of [SystemService] singletons and configured through [Global<NodeConfig>](crate::global::Global), see [crate::start]
+    pub fn system() -> Node {
+        Node {
+            controller: NodeController::from_registry(),
+            registry: ProcessRegistry::from_registry(),
+            memkv: MemKv::from_registry(),
+        }
+    }
+
+    /// Node the current task runs on, [Node::system] if not running inside [Node::scope].
+    ///
+    /// Once nodes were started with [Node::start], falling back to the default node usually means a task
+    /// was spawned outside the scope of its node, and is logged. Use `Node::system().scope(..)` to run
+    /// on the default node deliberately, or [Node::try_current] to fail instead
+    pub fn current() -> Node {
+        Self::try_current().unwrap_or_else(|| {
+            if STARTED.load(Ordering::Relaxed) > 0 {
+                log::warn!("Task runs outside of Node::scope while other nodes are running, using the default node");
+            }
+            Node::system()
+        })
+    }
+
+    /// Node the current task runs on, `None` if not running inside [Node::scope]
+    pub fn try_current() -> Option<Node> {
+        CURRENT.try_with(|node| node.clone()).ok()
+    }
+
+    /// Run `fut` on this node.
+    ///
+    /// Processes started and remote messages sent from within `fut` use this node
+    pub fn scope<F: Future>(&self, fut: F) -> impl Future<Output=F::Output> {
+        CURRENT.scope(self.clone(), fut)
+    }
+
+    pub fn controller(&self) -> &Addr<NodeController> {
+        &self.controller
+    }
+
+    pub fn registry(&self) -> &Addr<ProcessRegistry> {
+        &self.registry
+    }
+
+    pub fn memkv(&self) -> &Addr<MemKv> {
+        &self.memkv
+    }
+}
@@ -1,121 +1,638 @@
 use crate::{
     import::*,
     Broadcast,
-    node::NodeController,
+    node::Node,
     node::FromNode,
     node::NodeConfig,
-    node::NodeStatus,
     proto::Meta,
+    proto::Auth,
+    proto::Chunk,
     proto::Net,
     proto::Request,
     proto::Response,
     proto::PingPong,
-    process::registry::ProcessRegistry,
-    global::Global,
-    global::Get,
+    proto::Peers,
+    proto::Goodbye,
+    proto::CloseReason,
+    proto::Metadata,
     util::RpcMethod,
     util::uuid,
     MethodCall,
 };
 
 use std::io;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use once_cell::sync::Lazy;
 use actix::io::{FramedWrite, WriteHandler};
-use tokio::{
-    net::tcp::OwnedWriteHalf,
-    net::TcpStream,
-};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::{Certificate, Session};
 use actix::Running;
-use futures::io::Error;
+use tokio::time::Instant;
 use crate::process::DispatchError;
+use crate::node::stats::{LinkCounters, LinkStats};
+use crate::node::tls::{self, Tls};
+use crate::node::auth::{self, Role, Transcript};
+use crate::node::caps::{self, Capabilities, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, QUIX_VERSION};
+use crate::node::codec::NetCodec;
+use crate::node::transport::{BoxConnection, NodeAddr};
+use crate::node::gossip::PeerList;
+use crate::node::metadata::PeerMetadata;
+
+/// Reason why a link to a remote node was closed
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// Remote node closed the connection
+    Closed,
+    /// Reading from or writing to the connection failed
+    Io(io::ErrorKind),
+    /// Remote node sent data which could not be decoded, or exceeded size limits
+    Protocol,
+    /// Nothing was received from the remote node within [NodeConfig::heartbeat_timeout]
+    Unreachable,
+    /// Both nodes dialed each other, and the other link to the node was kept
+    Duplicate,
+    /// Name of the remote node is already used by another node of the cluster
+    NameTaken,
+    /// Node was not linked when it started being monitored
+    NotLinked,
+    /// Node left the cluster
+    Shutdown,
+    /// Node was restarted, and a new instance with the same name and address replaced it
+    Restarted,
+}
+
+impl From<&io::Error> for DisconnectReason {
+    fn from(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::InvalidData => DisconnectReason::Protocol,
+            kind => DisconnectReason::Io(kind),
+        }
+    }
+}
+
+/// Error establishing a link to a remote node
+#[derive(Debug)]
+pub enum LinkError {
+    /// Connection could not be opened, or failed during the handshake
+    Io(io::Error),
+    /// Remote node did not follow the handshake protocol
+    Handshake(&'static str),
+    /// TLS could not be set up, or the remote node was not trusted
+    Tls(String),
+    /// Certificate of the remote node was not issued for its node id
+    Identity(Uuid),
+    /// Remote node does not know the cluster cookie
+    Auth,
+    /// Remote node uses the same name as this node, or another node of the cluster
+    NameTaken(String),
+    /// Remote node speaks no version of the protocol this node does
+    Incompatible {
+        /// Newest protocol version of the remote node
+        protocol: u32,
+        /// Oldest protocol version of the remote node
+        min_protocol: u32,
+        version: String,
+    },
+    /// This node is shutting down, and does not establish new links
+    ShuttingDown,
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkError::Io(e) => write!(f, "I/O error: {}", e),
+            LinkError::Handshake(e) => write!(f, "Handshake failed: {}", e),
+            LinkError::Tls(e) => write!(f, "TLS error: {}", e),
+            LinkError::Identity(id) => write!(f, "Certificate does not match node id: {}", id),
+            LinkError::Auth => write!(f, "Cookie authentication failed"),
+            LinkError::NameTaken(name) => write!(f, "Node name already taken: {}", name),
+            LinkError::ShuttingDown => write!(f, "Node is shutting down"),
+            LinkError::Incompatible { protocol, min_protocol, version } => {
+                write!(f, "Incompatible protocol versions {}..={} (quix {}), supported {}..={}",
+                       min_protocol, protocol, version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
 
+impl From<io::Error> for LinkError {
+    fn from(e: io::Error) -> Self {
+        LinkError::Io(e)
+    }
+}
+
+/// Remote node on the other side of an established link
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub id: Uuid,
+    pub name: Option<String>,
+    /// Address the connection was made to, or accepted from
+    pub addr: NodeAddr,
+    /// Address the node accepts connections on, if it announced a valid one
+    pub listen: Option<NodeAddr>,
+    /// Node does not take part in forming the mesh
+    pub hidden: bool,
+    pub metadata: Metadata,
+    /// We dialed the node
+    pub outbound: bool,
+    /// Sequence number of the link, chosen by the dialing node. Newer links get higher numbers
+    pub seq: u64,
+    /// Version of the wire protocol used on the link, the newest one both nodes speak
+    pub protocol: u32,
+    /// Features supported by both nodes, and used on the link
+    pub caps: Capabilities,
+}
+
+impl std::fmt::Display for Peer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{} ({})", name, self.id),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+
+/// Next link sequence number. Starts at current time, so links dialed after a restart are newer
+static LINK_SEQ: Lazy<AtomicU64> = Lazy::new(|| {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    AtomicU64::new(now.as_nanos() as u64)
+});
+
+/// Write half of the connection, either plain or wrapped in TLS
+type LinkWriter = Box<dyn AsyncWrite + Unpin>;
 
 pub struct NodeLink {
+    /// Local node this link belongs to
+    node: Node,
     id: Uuid,
+    /// Reported to the [NodeController](crate::node::NodeController) once this link stops
+    reason: DisconnectReason,
+    /// Features supported by both sides of the link
+    caps: Capabilities,
     correlation_counter: i64,
-    stream: FramedWrite<Net, OwnedWriteHalf, NetCodec>,
+    request_timeout: Duration,
+    heartbeat_timeout: Duration,
+    /// Time we last received any frame from the remote node
+    last_seen: Instant,
+    counters: LinkCounters,
+    stream: FramedWrite<Net, LinkWriter, NetCodec>,
     running: HashMap<i64, Sender<Result<Bytes, DispatchError>>>,
+    /// Number of requests from the remote node we are processing
+    serving: usize,
+    /// Node is shutting down, new requests from the remote node are rejected
+    draining: bool,
+    /// Notified once no requests are in flight in either direction
+    drained: Vec<Sender<()>>,
+    chunk_size: usize,
+    max_frame_size: usize,
+    max_message_size: usize,
+    chunk_counter: u64,
+    /// Encoded frames being sent in chunks with their full size, the first one sends its next chunk
+    outgoing: VecDeque<(u64, Bytes, usize)>,
+    /// Full size of frames in `outgoing`, which the remote node buffers until their last chunk arrives
+    outgoing_bytes: usize,
+    /// Encoded frames waiting until the remote node can buffer them
+    queued: VecDeque<Bytes>,
+    /// Partially received chunked frames
+    incoming: HashMap<u64, BytesMut>,
+    /// Size of partially received frames
+    incoming_bytes: usize,
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct NetCodec;
+/// Number of chunked frames which can be partially received on a link at once
+pub const MAX_PARTIAL_FRAMES: usize = 64;
 
-impl tokio_util::codec::Encoder<Net> for NetCodec {
-    type Error = io::Error;
+/// Link stopped, reported to the [NodeController](crate::node::NodeController)
+pub(crate) struct LinkDown {
+    pub id: Uuid,
+    pub link: Addr<NodeLink>,
+    pub reason: DisconnectReason,
+}
 
-    fn encode(&mut self, item: Net, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let len = prost::Message::encoded_len(&item);
-        dst.put_u32(len as _);
+impl Message for LinkDown {
+    type Result = ();
+}
 
-        prost::Message::encode(&item, dst)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-    }
+/// Close the link, failing requests waiting for responses. Remote node is told the reason
+pub(crate) struct Close(pub DisconnectReason);
+
+impl Message for Close {
+    type Result = ();
 }
 
-impl tokio_util::codec::Decoder for NetCodec {
-    type Item = Net;
-    type Error = io::Error;
+/// Stop accepting requests from the remote node, and tell it we are leaving.
+///
+/// Resolves once no requests are in flight in either direction
+pub(crate) struct Drain;
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < 4 {
-            return Ok(None);
-        }
-        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
-        if src.len() < len + 4 {
-            return Ok(None);
-        }
-        let len = src.get_u32() as usize;
-        let msg = src.split_to(len);
+impl Message for Drain {
+    type Result = ();
+}
+
+/// Send the next chunk of a large frame, other messages can be written in between
+struct SendChunk;
+
+impl Message for SendChunk {
+    type Result = ();
+}
+
+/// Connection closed during the handshake, like when the remote node restarts
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed during handshake")
+}
 
-        prost::Message::decode(msg)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-            .map(Some)
+/// TLS errors are reported as invalid data, other ones come from the connection
+fn tls_error(e: io::Error) -> LinkError {
+    match e.kind() {
+        io::ErrorKind::InvalidData => LinkError::Tls(e.to_string()),
+        _ => LinkError::Io(e),
     }
 }
 
 impl NodeLink {
     /// Open connection to a node, exchange IDs, setup local state
-    pub async fn new(socket: TcpStream) -> (Uuid, SocketAddr, Addr<Self>) {
-        let codec = NetCodec;
+    ///
+    /// `outbound` is set if we dialed the remote node, and decides our role in TLS handshake.
+    /// `tls` is the [NodeConfig::tls] loaded by the controller.
+    /// Fails with [io::ErrorKind::TimedOut] if the handshake does not complete within [NodeConfig::handshake_timeout]
+    pub(crate) async fn new(node: Node, v: NodeConfig, tls: Option<Arc<Tls>>, socket: BoxConnection, peer_addr: NodeAddr, outbound: bool) -> Result<(Peer, Addr<Self>), LinkError> {
+        let timeout = v.handshake_timeout;
+        match tokio::time::timeout(timeout, Self::establish(node, v, tls, socket, peer_addr, outbound)).await {
+            Ok(res) => res,
+            Err(_) => Err(LinkError::Io(io::Error::new(io::ErrorKind::TimedOut, "Handshake timed out"))),
+        }
+    }
 
-        socket.set_nodelay(true).unwrap();
+    async fn establish(node: Node, v: NodeConfig, tls: Option<Arc<Tls>>, socket: BoxConnection, peer_addr: NodeAddr, outbound: bool) -> Result<(Peer, Addr<Self>), LinkError> {
+        let tls = match (&v.tls, tls) {
+            (Some(_), Some(tls)) => tls,
+            (Some(_), None) => return Err(LinkError::Tls("TLS configuration is not loaded".to_string())),
+            (None, _) => return Self::handshake(node, v, socket, peer_addr, outbound, None).await,
+        };
+
+        if outbound {
+            let stream = tls.connector.connect(Tls::server_name(), socket).await.map_err(tls_error)?;
+            let certs = stream.get_ref().1.get_peer_certificates();
+            Self::handshake(node, v, stream, peer_addr, outbound, certs).await
+        } else {
+            let stream = tls.acceptor.accept(socket).await.map_err(tls_error)?;
+            let certs = stream.get_ref().1.get_peer_certificates();
+            Self::handshake(node, v, stream, peer_addr, outbound, certs).await
+        }
+    }
 
-        let v = Global::<NodeConfig>::from_registry().send(Get::default()).await.unwrap().unwrap();
-        let meta = Meta { nodeid: v.id.as_bytes().to_vec() };
+    /// Exchange node metadata over established connection, and start the link actor
+    async fn handshake<S>(node: Node, v: NodeConfig, socket: S, peer_addr: NodeAddr, outbound: bool, certs: Option<Vec<Certificate>>) -> Result<(Peer, Addr<Self>), LinkError>
+    where S: AsyncRead + AsyncWrite + Unpin + 'static
+    {
+        let codec = NetCodec::default().max_frame_size(v.max_frame_size);
+
+        let request_timeout = v.request_timeout;
+        let heartbeat_interval = v.heartbeat_interval;
+        let heartbeat_timeout = v.heartbeat_timeout;
+        let chunk_size = v.chunk_size;
+        let max_frame_size = v.max_frame_size;
+        let max_message_size = v.max_message_size;
+        let mut local_caps = Capabilities::CHUNKING;
+        if v.tls.is_some() {
+            local_caps = local_caps | Capabilities::TLS;
+        }
+        if v.cookie.is_some() {
+            local_caps = local_caps | Capabilities::AUTH;
+        }
+        if v.compression.is_some() {
+            local_caps = local_caps | Capabilities::COMPRESSION;
+        }
 
-        let peer_addr = socket.peer_addr().unwrap();
+        let nonce = v.cookie.as_ref().map(|_| auth::nonce());
+        let seq = if outbound { Some(LINK_SEQ.fetch_add(1, Ordering::Relaxed)) } else { None };
+        let meta = Meta {
+            nodeid: v.id.as_bytes().to_vec(),
+            nonce: nonce.clone(),
+            protocol: Some(PROTOCOL_VERSION),
+            min_protocol: Some(MIN_PROTOCOL_VERSION),
+            version: Some(QUIX_VERSION.to_string()),
+            capabilities: Some(local_caps.0),
+            listen: v.advertised().map(|addr| addr.to_string()),
+            hidden: Some(v.hidden),
+            link: seq,
+            name: v.name.clone(),
+            metadata: Some(v.local_metadata()),
+        };
 
-        let (rx, tx) = socket.into_split();
+        let (rx, tx) = tokio::io::split(socket);
 
-        let mut tx = tokio_util::codec::FramedWrite::new(tx, codec);
+        let mut tx = tokio_util::codec::FramedWrite::new(tx, codec.clone());
         let mut rx = tokio_util::codec::FramedRead::new(rx, codec);
 
-        tx.send(Net { meta: Some(meta), ..Default::default() }).await.unwrap();
+        tx.send(Net { meta: Some(meta), ..Default::default() }).await?;
 
-        let other = rx.next().await.unwrap().unwrap().meta.unwrap();
+        let other = rx.next().await.ok_or_else(closed)??;
+        let other = other.meta.ok_or(LinkError::Handshake("missing node metadata"))?;
+        if other.nodeid.len() != 16 {
+            return Err(LinkError::Handshake("invalid node id"));
+        }
         let id: Uuid = uuid(other.nodeid.as_slice());
+        if id == v.id {
+            return Err(LinkError::Handshake("connected to itself"));
+        }
+
+        let version = other.version.clone().unwrap_or_default();
+        let max_protocol = other.protocol.unwrap_or(0);
+        // Nodes which only speak a single version don't send the lower bound
+        let min_protocol = other.min_protocol.unwrap_or(max_protocol);
+        let protocol = match caps::negotiate(min_protocol, max_protocol) {
+            Some(protocol) => protocol,
+            None => return Err(LinkError::Incompatible { protocol: max_protocol, min_protocol, version }),
+        };
+        let remote_caps = Capabilities(other.capabilities.unwrap_or(0));
+        if local_caps.contains(Capabilities::AUTH) != remote_caps.contains(Capabilities::AUTH) {
+            // One of the nodes has no cookie, don't wait for an answer which will never come
+            return Err(LinkError::Auth);
+        }
+        let caps = local_caps & remote_caps;
+        if other.name.is_some() && other.name == v.name {
+            return Err(LinkError::NameTaken(other.name.unwrap_or_default()));
+        }
+        let listen = other.listen.as_ref()
+            .and_then(|l| l.parse::<NodeAddr>().ok())
+            .map(|l| l.seen_at(&peer_addr));
+        let peer = Peer {
+            id,
+            name: other.name.clone(),
+            addr: peer_addr,
+            listen,
+            hidden: other.hidden.unwrap_or(false),
+            metadata: other.metadata.clone().unwrap_or_default(),
+            outbound,
+            seq: seq.or(other.link).unwrap_or(0),
+            protocol,
+            caps,
+        };
+        log::info!("Node {} runs quix {}, using protocol {}, common capabilities: {:?}", peer, version, protocol, caps);
+
+        if v.tls.as_ref().map_or(false, |tls| tls.verify_node_id) {
+            if !certs.map_or(false, |certs| tls::verify_node_id(&certs, id)) {
+                return Err(LinkError::Identity(id));
+            }
+        }
+
+        if let (Some(cookie), Some(nonce)) = (&v.cookie, &nonce) {
+            let other_nonce = other.nonce.as_deref().ok_or(LinkError::Auth)?;
+            let (role, transcript) = if outbound {
+                (Role::Dialer, Transcript { dialer: v.id, acceptor: id, dialer_nonce: nonce, acceptor_nonce: other_nonce })
+            } else {
+                (Role::Acceptor, Transcript { dialer: id, acceptor: v.id, dialer_nonce: other_nonce, acceptor_nonce: nonce })
+            };
+            let own = Net {
+                auth: Some(Auth { digest: auth::digest(cookie, role, &transcript) }),
+                ..Default::default()
+            };
+
+            // Dialer proves itself first. Acceptor answers only after checking the proof,
+            // so its digest can't be obtained without knowing the cookie and relayed elsewhere
+            if outbound {
+                tx.send(own.clone()).await?;
+            }
+            let answer = rx.next().await.ok_or_else(closed)??;
+            if answer.goodbye.is_some() {
+                // Acceptor rejected our digest
+                return Err(LinkError::Auth);
+            }
+            let answer = answer.auth.ok_or(LinkError::Auth)?;
+            if !auth::verify(cookie, role.peer(), &transcript, &answer.digest) {
+                if !outbound {
+                    let reason = Some(CloseReason::Unauthorized as i32);
+                    tx.send(Net { goodbye: Some(Goodbye { reason }), ..Default::default() }).await?;
+                }
+                return Err(LinkError::Auth);
+            }
+            if !outbound {
+                tx.send(own).await?;
+            }
+        }
 
-        let tx = tx.into_inner();
+        let tx: LinkWriter = Box::new(tx.into_inner());
+        // We can always read compressed frames, but only send them if the remote node accepts them
+        let write_codec = match v.compression {
+            Some(ref cfg) if caps.contains(Capabilities::COMPRESSION) => NetCodec::compressed(cfg.clone()),
+            _ => NetCodec::default(),
+        };
 
         let this = Actor::create(|ctx| {
             ctx.add_stream(rx);
 
-            let tx = FramedWrite::new(tx, NetCodec, ctx);
-            ctx.run_interval(Duration::from_secs(1), |this: &mut Self, ctx| {
+            let tx = FramedWrite::new(tx, write_codec, ctx);
+            ctx.run_interval(heartbeat_interval, |this: &mut Self, ctx| {
+                if this.last_seen.elapsed() > this.heartbeat_timeout {
+                    log::error!("Node {} missed heartbeats for {:?}, disconnecting", this.id, this.last_seen.elapsed());
+                    this.reason = DisconnectReason::Unreachable;
+                    ctx.stop();
+                    return;
+                }
+
                 log::trace!("Pinging");
-                this.stream.write(Net {
+                this.write(ctx, Net {
                     ping: Some(PingPong {}),
                     ..Default::default()
                 });
             });
 
             NodeLink {
+                node,
                 id: id.clone(),
+                reason: DisconnectReason::Closed,
+                caps,
                 correlation_counter: 0,
+                request_timeout,
+                heartbeat_timeout,
+                last_seen: Instant::now(),
+                counters: LinkCounters::default(),
                 stream: tx,
                 running: HashMap::new(),
+                serving: 0,
+                draining: false,
+                drained: vec![],
+                chunk_size,
+                max_frame_size,
+                max_message_size,
+                chunk_counter: 0,
+                outgoing: VecDeque::new(),
+                outgoing_bytes: 0,
+                queued: VecDeque::new(),
+                incoming: HashMap::new(),
+                incoming_bytes: 0,
             }
         });
-        (id, peer_addr, this)
+        Ok((peer, this))
+    }
+
+    /// Send a message, frames larger than chunk size are split if the remote node can reassemble them
+    fn write(&mut self, ctx: &mut Context<Self>, msg: Net) {
+        let len = prost::Message::encoded_len(&msg);
+        if len > self.chunk_size && self.caps.contains(Capabilities::CHUNKING) {
+            let mut buf = BytesMut::with_capacity(len);
+            // Buffer has enough capacity, encoding can't fail
+            prost::Message::encode(&msg, &mut buf).unwrap();
+
+            let idle = self.outgoing.is_empty() && self.queued.is_empty();
+            self.queued.push_back(buf.freeze());
+            if idle {
+                ctx.notify(SendChunk);
+            }
+            return;
+        }
+        self.write_frame(msg);
+    }
+
+    /// Whether the remote node accepts `msg`, either as a single frame or reassembled from chunks.
+    ///
+    /// Oversized requests and responses fail on their own, instead of making the remote node close the link
+    fn fits(&self, msg: &Net) -> bool {
+        let limit = if self.caps.contains(Capabilities::CHUNKING) {
+            self.max_message_size
+        } else {
+            self.max_frame_size
+        };
+        prost::Message::encoded_len(msg) <= limit
+    }
+
+    fn write_frame(&mut self, msg: Net) {
+        self.counters.sent(&msg);
+        self.stream.write(msg);
+    }
+
+    /// Write the next chunk of a large frame, returns whether it was the last one
+    fn write_chunk(&mut self, id: u64, data: &mut Bytes) -> bool {
+        let part = data.split_to(self.chunk_size.min(data.len()));
+        let last = data.is_empty();
+        self.write_frame(Net {
+            chunk: Some(Chunk {
+                id,
+                data: part.to_vec(),
+                last: Some(last),
+            }),
+            ..Default::default()
+        });
+        last
+    }
+
+    /// Write all chunks of large frames right away, one frame after another
+    fn flush_chunks(&mut self) {
+        while let Some((id, mut data, _)) = self.outgoing.pop_front() {
+            while !self.write_chunk(id, &mut data) {}
+        }
+        while let Some(mut data) = self.queued.pop_front() {
+            self.chunk_counter = self.chunk_counter.wrapping_add(1);
+            while !self.write_chunk(self.chunk_counter, &mut data) {}
+        }
+        self.outgoing_bytes = 0;
+    }
+
+    fn handle_chunk(&mut self, ctx: &mut Context<Self>, chunk: Chunk) {
+        if !self.incoming.contains_key(&chunk.id) && self.incoming.len() >= MAX_PARTIAL_FRAMES {
+            log::error!("Node {} sent more than {} partial frames, disconnecting", self.id, MAX_PARTIAL_FRAMES);
+            self.reason = DisconnectReason::Protocol;
+            ctx.stop();
+            return;
+        }
+        // Bounds both a single message, and all partial messages together
+        if self.incoming_bytes + chunk.data.len() > self.max_message_size {
+            log::error!("Node {} sent messages larger than {} bytes, disconnecting", self.id, self.max_message_size);
+            self.reason = DisconnectReason::Protocol;
+            ctx.stop();
+            return;
+        }
+        self.incoming_bytes += chunk.data.len();
+        self.incoming.entry(chunk.id).or_insert_with(BytesMut::new).extend_from_slice(&chunk.data);
+        if !chunk.last.unwrap_or(false) {
+            return;
+        }
+
+        let buf = self.incoming.remove(&chunk.id).unwrap();
+        self.incoming_bytes -= buf.len();
+        match <Net as prost::Message>::decode(buf.freeze()) {
+            // Chunks are never nested
+            Ok(msg) if msg.chunk.is_none() => self.handle_frame(ctx, msg),
+            _ => {
+                log::error!("Node {} sent malformed chunked frame, disconnecting", self.id);
+                self.reason = DisconnectReason::Protocol;
+                ctx.stop();
+            }
+        }
+    }
+
+    fn handle_frame(&mut self, ctx: &mut Context<Self>, msg: Net) {
+        if let Some(ping) = msg.ping {
+            log::trace!("Got pinged");
+            self.write(ctx, Net {
+                pong: Some(ping),
+                ..Default::default()
+            });
+        }
+
+        if let Some(mut req) = msg.request {
+            self.handle_request(ctx, req);
+        }
+
+        if let Some(res) = msg.response {
+            if let Some(tx) = self.running.remove(&res.correlation) {
+                if let Some(err) = res.error {
+                    // TODO: Error from i32
+                    let _ = tx.send(Err(DispatchError::from_code(err)));
+                } else if let Some(body) = res.body {
+                    let _ = tx.send(Ok(Bytes::from(body)));
+                } else {
+                    log::error!("Received response without error or body");
+                    let _ = tx.send(Err(DispatchError::Protocol));
+                }
+            } else {
+                log::warn!("Response to unknown or timed out request: {}", res.correlation)
+            }
+            self.check_drained();
+        }
+
+        if let Some(peers) = msg.peers {
+            self.node.controller().do_send(FromNode {
+                node_id: self.id,
+                inner: PeerList::from(peers),
+            });
+        }
+
+        if let Some(metadata) = msg.metadata {
+            self.node.controller().do_send(FromNode {
+                node_id: self.id,
+                inner: PeerMetadata(metadata),
+            });
+        }
+
+        if let Some(chunk) = msg.chunk {
+            self.handle_chunk(ctx, chunk);
+        }
+
+        if let Some(goodbye) = msg.goodbye {
+            self.reason = match goodbye.reason.and_then(CloseReason::from_i32) {
+                Some(CloseReason::Duplicate) => DisconnectReason::Duplicate,
+                Some(CloseReason::NameTaken) => DisconnectReason::NameTaken,
+                Some(CloseReason::Shutdown) => {
+                    // Remote node closes the link once requests in flight are answered,
+                    // until then the controller stops using it
+                    self.reason = DisconnectReason::Shutdown;
+                    self.node.controller().do_send(LinkDown {
+                        id: self.id,
+                        link: ctx.address(),
+                        reason: DisconnectReason::Shutdown,
+                    });
+                    return;
+                }
+                Some(CloseReason::Unauthorized) | None => DisconnectReason::Closed,
+            };
+            ctx.stop();
+        }
     }
 
     fn handle_return_correlation(&mut self, ctx: &mut Context<Self>, res: Result<Bytes, DispatchError>, corr: i64) {
@@ -129,36 +646,74 @@ impl NodeLink {
             body: ok,
             error: err,
         };
-        let msg = Net {
+        let mut msg = Net {
             response: Some(res),
             ..Default::default()
         };
-        self.stream.write(msg);
+        if !self.fits(&msg) {
+            log::warn!("Response to node {} is too large, sending error instead", self.id);
+            msg.response = Some(Response {
+                correlation: corr,
+                body: None,
+                error: Some(DispatchError::MessageTooLarge.code()),
+            });
+        }
+        self.write(ctx, msg);
+    }
+
+    /// Run locally dispatched request, and send its result back to the remote node
+    fn serve(&mut self, ctx: &mut Context<Self>, work: BoxFuture<'static, Result<Bytes, DispatchError>>, corr: i64) {
+        self.serving += 1;
+        let work = wrap_future(work).map(move |res, this: &mut Self, ctx| {
+            this.handle_return_correlation(ctx, res, corr);
+            this.serving -= 1;
+            this.check_drained();
+        });
+        ctx.spawn(work);
+    }
+
+    fn check_drained(&mut self) {
+        // Large responses are only sent once all their chunks are
+        let sending = !self.outgoing.is_empty() || !self.queued.is_empty();
+        if self.running.is_empty() && self.serving == 0 && !sending {
+            for tx in self.drained.drain(..) {
+                let _ = tx.send(());
+            }
+        }
     }
 
     fn handle_request(&mut self, ctx: &mut Context<Self>, req: Request) {
         log::trace!("Received request");
 
+        if self.draining {
+            if let Some(corr) = req.correlation {
+                self.handle_return_correlation(ctx, Err(DispatchError::NodeNotFound), corr);
+            }
+            return;
+        }
+
         let procid: Option<Uuid> = req.procid.map(uuid).filter(|v| !v.is_nil());
 
+        let timeout = req.timeout.map(|ms| Duration::from_millis(ms as u64));
         let dispatch = MethodCall {
             procid,
             method: req.methodid,
             body: Bytes::from(req.body),
+            timeout,
         };
 
         if let Some(procid) = procid {
-            let procreg = ProcessRegistry::from_registry();
+            let procreg = self.node.registry().clone();
 
             if let Some(corr) = req.correlation {
-                let work = wrap_future(procreg.send(dispatch).map(|r| r.unwrap()));
-                let work = work.map(move |res, this: &mut Self, ctx| this.handle_return_correlation(ctx, res, corr));
-                ctx.spawn(work);
+                let work = with_timeout(timeout, procreg.send(dispatch).map(|r| r.unwrap_or(Err(DispatchError::MailboxLocal))));
+                self.serve(ctx, work, corr);
             } else {
-                procreg.do_send(dispatch);
+                // Response of the handler is only produced if someone waits for it
+                ctx.spawn(wrap_future(procreg.send(dispatch).map(|_| ())));
             }
         } else {
-            let nodecontrol = NodeController::from_registry();
+            let nodecontrol = self.node.controller().clone();
 
             let dispatch = FromNode {
                 node_id: self.id,
@@ -166,14 +721,12 @@ impl NodeLink {
             };
 
             if let Some(corr) = req.correlation {
-                let work = wrap_future(nodecontrol.send(dispatch).map(|r| r.unwrap()));
-                let work = work.map(move |res, this: &mut Self, ctx| this.handle_return_correlation(ctx, res, corr));
-                ctx.spawn(work);
+                let work = with_timeout(timeout, nodecontrol.send(dispatch).map(|r| r.unwrap_or(Err(DispatchError::MailboxLocal))));
+                self.serve(ctx, work, corr);
             } else {
-                // Err, here it should be a broadcast
-                //ctx.spawn(wrap_future(async move { nodecontrol.send(dispatch).await.unwrap().unwrap(); }));
-                nodecontrol.do_send(dispatch);
-                // Without process ID, we currently only handle notifications
+                // Without process ID, we currently only handle notifications.
+                // Global handlers forward them to other actors, which drop messages nobody waits for
+                ctx.spawn(wrap_future(nodecontrol.send(dispatch).map(|_| ())));
             }
         }
     }
@@ -183,13 +736,22 @@ impl Actor for NodeLink {
     type Context = Context<Self>;
 
     fn stopped(&mut self, ctx: &mut Self::Context) {
-        NodeController::from_registry().do_send(NodeStatus::Disconnected(self.id));
+        // Nobody is going to answer these anymore, let the callers know right away
+        for (_, tx) in self.running.drain() {
+            let _ = tx.send(Err(DispatchError::ConnectionLost));
+        }
+        self.node.controller().do_send(LinkDown {
+            id: self.id,
+            link: ctx.address(),
+            reason: self.reason.clone(),
+        });
     }
 }
 
 impl WriteHandler<std::io::Error> for NodeLink {
-    fn error(&mut self, err: Error, ctx: &mut Self::Context) -> Running {
-        panic!("Invalid data {:?}", err);
+    fn error(&mut self, err: io::Error, ctx: &mut Self::Context) -> Running {
+        log::error!("Writing to node {} failed, disconnecting: {}", self.id, err);
+        self.reason = DisconnectReason::from(&err);
         // Stop on errors
         Running::Stop
     }
@@ -200,40 +762,124 @@ impl StreamHandler<io::Result<Net>> for NodeLink {
         let msg = match item {
             Ok(item) => item,
             Err(error) => {
-                log::error!("Error occured, disconnecting");
-                panic!("Invalid data {:?}", error);
+                log::error!("Reading from node {} failed, disconnecting: {}", self.id, error);
+                self.reason = DisconnectReason::from(&error);
                 ctx.stop();
                 return;
             }
         };
+        self.last_seen = Instant::now();
+        self.counters.received(&msg);
+        self.handle_frame(ctx, msg);
+    }
+}
 
-        if let Some(ping) = msg.ping {
-            log::trace!("Got pinged");
-            self.stream.write(Net {
-                pong: Some(ping),
-                ..Default::default()
-            });
-        }
+impl Handler<SendChunk> for NodeLink {
+    type Result = ();
 
-        if let Some(mut req) = msg.request {
-            self.handle_request(ctx, req);
+    fn handle(&mut self, _: SendChunk, ctx: &mut Self::Context) {
+        // Start sending queued frames, as long as the remote node accepts them
+        while let Some(size) = self.queued.front().map(Bytes::len) {
+            let full = self.outgoing.len() >= MAX_PARTIAL_FRAMES || self.outgoing_bytes + size > self.max_message_size;
+            if full && !self.outgoing.is_empty() {
+                break;
+            }
+            let data = self.queued.pop_front().unwrap();
+            self.chunk_counter = self.chunk_counter.wrapping_add(1);
+            self.outgoing_bytes += size;
+            self.outgoing.push_back((self.chunk_counter, data, size));
         }
 
-        if let Some(res) = msg.response {
-            if let Some(tx) = self.running.remove(&res.correlation) {
-                if let Some(err) = res.error {
-                    // TODO: Error from i32
-                    let _ = tx.send(Err(DispatchError::from_code(err)));
-                } else if let Some(body) = res.body {
-                    let _ = tx.send(Ok(Bytes::from(body)));
-                } else {
-                    log::error!("Received response without error or body");
-                    let _ = tx.send(Err(DispatchError::Protocol));
-                }
+        if let Some((id, mut data, size)) = self.outgoing.pop_front() {
+            // Round robin between large frames, so one of them does not block the others
+            if self.write_chunk(id, &mut data) {
+                self.outgoing_bytes -= size;
             } else {
-                log::error!("Missing correlation id: {}", res.correlation)
+                self.outgoing.push_back((id, data, size));
             }
         }
+        if !self.outgoing.is_empty() || !self.queued.is_empty() {
+            ctx.notify(SendChunk);
+        } else {
+            self.check_drained();
+        }
+    }
+}
+
+impl Handler<Close> for NodeLink {
+    type Result = ();
+
+    fn handle(&mut self, msg: Close, ctx: &mut Self::Context) {
+        let reason = match msg.0 {
+            DisconnectReason::Duplicate => Some(CloseReason::Duplicate),
+            DisconnectReason::NameTaken => Some(CloseReason::NameTaken),
+            DisconnectReason::Shutdown => Some(CloseReason::Shutdown),
+            _ => None,
+        };
+        self.reason = msg.0;
+        // Goodbye is the last frame the remote node receives
+        self.flush_chunks();
+        self.write_frame(Net {
+            goodbye: Some(Goodbye { reason: reason.map(|r| r as i32) }),
+            ..Default::default()
+        });
+        // Link stops once the goodbye is flushed
+        self.stream.close();
+    }
+}
+
+impl Handler<Drain> for NodeLink {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, msg: Drain, ctx: &mut Self::Context) -> Self::Result {
+        if !self.draining {
+            self.draining = true;
+            self.write(ctx, Net {
+                goodbye: Some(Goodbye { reason: Some(CloseReason::Shutdown as i32) }),
+                ..Default::default()
+            });
+        }
+        let (tx, rx) = futures::channel::oneshot::channel();
+        self.drained.push(tx);
+        self.check_drained();
+        Box::pin(rx.map(|_| ()))
+    }
+}
+
+impl Handler<PeerList> for NodeLink {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerList, ctx: &mut Self::Context) {
+        self.write(ctx, Net {
+            peers: Some(Peers::from(&msg)),
+            ..Default::default()
+        });
+    }
+}
+
+impl Handler<PeerMetadata> for NodeLink {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerMetadata, ctx: &mut Self::Context) {
+        self.write(ctx, Net {
+            metadata: Some(msg.0),
+            ..Default::default()
+        });
+    }
+}
+
+/// Read statistics of a single link
+pub(crate) struct ReadStats;
+
+impl Message for ReadStats {
+    type Result = LinkStats;
+}
+
+impl Handler<ReadStats> for NodeLink {
+    type Result = actix::MessageResult<ReadStats>;
+
+    fn handle(&mut self, msg: ReadStats, ctx: &mut Self::Context) -> Self::Result {
+        actix::MessageResult(self.counters.stats(self.running.len(), self.last_seen))
     }
 }
 
@@ -247,6 +893,7 @@ impl Handler<Broadcast> for NodeLink {
 
             methodid: msg.method,
             body: msg.body.to_vec(),
+            timeout: None,
         };
 
         let netreq = Net {
@@ -254,7 +901,7 @@ impl Handler<Broadcast> for NodeLink {
             ..Default::default()
         };
 
-        self.stream.write(netreq);
+        self.write(ctx, netreq);
         Ok(())
     }
 }
@@ -263,37 +910,55 @@ impl Handler<MethodCall> for NodeLink {
     type Result = actix::Response<Bytes, DispatchError>;
 
     fn handle(&mut self, msg: MethodCall, ctx: &mut Context<Self>) -> Self::Result {
+        let timeout = msg.timeout.unwrap_or(self.request_timeout);
         let mut req = Request {
             correlation: None,
             procid: msg.procid.map(|id| id.as_bytes().to_vec()),
             methodid: msg.method,
             body: msg.body.to_vec(),
+            timeout: Some(timeout.as_millis().min(u32::MAX as u128) as u32),
         };
-        return self.send_request(ctx, req);
+        return self.send_request(ctx, req, timeout);
     }
 }
 
 
 impl NodeLink {
-    pub(crate) fn send_request(&mut self, ctx: &mut Context<NodeLink>, mut req: Request) -> actix::Response<Bytes, DispatchError> {
+    pub(crate) fn send_request(&mut self, ctx: &mut Context<NodeLink>, mut req: Request, timeout: Duration) -> actix::Response<Bytes, DispatchError> {
         self.correlation_counter = self.correlation_counter.wrapping_add(1);
 
-        let (tx, rx) = futures::channel::oneshot::channel();
-        self.running.insert(self.correlation_counter, tx);
-
-        req.correlation = Some(self.correlation_counter);
+        let corr = self.correlation_counter;
+        req.correlation = Some(corr);
 
         let net = Net {
             request: Some(req),
             ..Default::default()
         };
+        if !self.fits(&net) {
+            return actix::Response::reply(Err(DispatchError::MessageTooLarge));
+        }
+
+        let (tx, rx) = futures::channel::oneshot::channel();
+        self.running.insert(corr, tx);
+        self.write(ctx, net);
+        // Safety timeout, the request is forgotten so a late response is ignored
+        ctx.run_later(timeout, move |this, _| {
+            if let Some(tx) = this.running.remove(&corr) {
+                let _ = tx.send(Err(DispatchError::Timeout));
+                this.check_drained();
+            }
+        });
+        actix::Response::fut(rx.map(|v| v.unwrap_or(Err(DispatchError::ConnectionLost))))
+    }
+}
 
-        self.stream.write(net);
-        // Safety timeout
-        let fut = tokio::time::timeout(Duration::from_secs(60), rx);
-        actix::Response::fut(fut
-            .map_err(|_| DispatchError::Timeout)
-            .map(|v| v.map(|v| v.map_err(|_| DispatchError::MailboxRemote))??)
-        )
+/// Give up on locally dispatched work once the remote caller stopped waiting for it
+fn with_timeout<F>(timeout: Option<Duration>, work: F) -> BoxFuture<'static, Result<Bytes, DispatchError>>
+where F: Future<Output=Result<Bytes, DispatchError>> + Send + 'static
+{
+    match timeout {
+        Some(timeout) => Box::pin(tokio::time::timeout(timeout, work)
+            .map(|r| r.unwrap_or(Err(DispatchError::Timeout)))),
+        None => Box::pin(work),
     }
 }
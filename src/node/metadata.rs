@@ -0,0 +1,78 @@
+use crate::import::*;
+
+use crate::node::{NodeController, FromNode};
+use crate::node::link::Peer;
+use crate::node::transport::NodeAddr;
+use crate::proto::Metadata;
+
+/// Information a node published about itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo {
+    pub id: Uuid,
+    pub name: Option<String>,
+    /// Address the node accepts connections on
+    pub addr: Option<NodeAddr>,
+    pub roles: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl NodeInfo {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+impl From<&Peer> for NodeInfo {
+    fn from(peer: &Peer) -> Self {
+        NodeInfo {
+            id: peer.id,
+            name: peer.name.clone(),
+            addr: peer.listen.clone(),
+            roles: peer.metadata.roles.clone(),
+            metadata: peer.metadata.values.clone(),
+        }
+    }
+}
+
+/// Replace roles and metadata of this node, and publish them to the cluster
+pub struct UpdateMetadata {
+    pub roles: Vec<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+impl Message for UpdateMetadata {
+    type Result = ();
+}
+
+impl Handler<UpdateMetadata> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateMetadata, ctx: &mut Context<Self>) {
+        self.config.roles = msg.roles;
+        self.config.metadata = msg.metadata;
+
+        let update = PeerMetadata(self.config.local_metadata());
+        for link in self.links.values() {
+            link.do_send(update.clone());
+        }
+    }
+}
+
+/// Metadata of a node, sent to its peers when it changes
+#[derive(Debug, Clone)]
+pub(crate) struct PeerMetadata(pub Metadata);
+
+impl Message for PeerMetadata {
+    type Result = ();
+}
+
+impl Handler<FromNode<PeerMetadata>> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: FromNode<PeerMetadata>, ctx: &mut Context<Self>) {
+        if let Some(peer) = self.peers.get_mut(&msg.node_id) {
+            log::info!("Node {} updated its metadata: {:?}", peer, msg.inner.0);
+            peer.metadata = msg.inner.0;
+        }
+    }
+}
@@ -1,26 +1,154 @@
 use crate::import::*;
 
-mod link;
+use std::path::PathBuf;
 
-use crate::node::link::NodeLink;
-use crate::util::{RegisterRecipient, RpcMethod};
+mod link;
+mod config;
+mod discovery;
+mod gossip;
+mod handle;
+mod metadata;
+mod monitor;
+mod seeds;
+mod auth;
+mod caps;
+mod codec;
+mod reconnect;
+mod stats;
+mod tls;
+mod transport;
+
+use crate::node::link::{NodeLink, ReadStats, Peer, LinkDown, Close, Drain};
+use crate::node::tls::Tls;
+use crate::util::{RegisterRecipient, UnregisterRecipient, RpcMethod};
 use crate::global::{Get, Global};
 use crate::process::{Dispatcher, DispatchError};
-use tokio::net::TcpStream;
 use crate::{Broadcast, NodeDispatch, MethodCall};
 use crate::process::registry::ProcessRegistry;
 
+pub use handle::Node;
+pub use reconnect::ReconnectConfig;
+pub use config::{ConfigError, CONFIG_ENV};
+pub use seeds::{SEEDS_ENV, parse_seeds, resolve_seeds};
+pub use discovery::DiscoveryConfig;
+pub use metadata::{NodeInfo, UpdateMetadata};
+pub use monitor::{MonitorNode, DemonitorNode, NodeDown};
+pub use link::{DisconnectReason, LinkError, MAX_PARTIAL_FRAMES};
+pub use stats::{LinkStats, RttStats};
+pub use tls::TlsConfig;
+pub use caps::{Capabilities, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, QUIX_VERSION, negotiate};
+pub use codec::{NetCodec, CompressionConfig, DEFAULT_MAX_FRAME_SIZE};
+pub use transport::{
+    NodeAddr, AddrParseError, Transport, Connection, BoxConnection, Listener,
+    TcpTransport, UnixTransport, MemoryTransport, MemoryStream,
+};
+
 #[derive(Debug, Clone)]
 pub struct NodeConfig {
     pub id: Uuid,
-    pub listen: SocketAddr,
+    /// Stable, human readable name of the node, like `worker-3`. Must be unique in the cluster
+    pub name: Option<String>,
+    /// Addresses to accept connections on, like an IPv4 and an IPv6 one.
+    /// Port 0 binds a free port, query the actual address with [ListenAddrs]
+    pub listen: Vec<NodeAddr>,
+    /// Address sent to other nodes instead of the first `listen` address,
+    /// when the node is reachable on a different one, like behind NAT or in a container
+    pub advertise: Option<NodeAddr>,
+    /// Backoff used when dialing nodes and re-establishing dropped links
+    pub reconnect: ReconnectConfig,
+    /// Timeout of remote calls, which did not specify their own
+    pub request_timeout: Duration,
+    /// Interval between pings sent over each link
+    pub heartbeat_interval: Duration,
+    /// Node is considered unreachable if we receive nothing from it for this long
+    pub heartbeat_timeout: Duration,
+    /// Connections which don't complete TLS and node handshake in time are dropped
+    pub handshake_timeout: Duration,
+    /// Encrypt and authenticate node links, plaintext TCP is used if not set
+    pub tls: Option<TlsConfig>,
+    /// Shared secret of the cluster. Nodes which can't prove they know it are rejected during handshake
+    pub cookie: Option<String>,
+    /// Compress large frames on links to nodes which enable it as well
+    pub compression: Option<CompressionConfig>,
+    /// Largest frame accepted from other nodes, links sending larger ones are closed
+    pub max_frame_size: usize,
+    /// Frames larger than this are split into chunks of this size, interleaved with other traffic.
+    /// Must be well below `max_frame_size` of all nodes in the cluster
+    pub chunk_size: usize,
+    /// Largest message which can be reassembled from chunks.
+    ///
+    /// Also bounds the size of all partially received messages of a link together,
+    /// and the size of large messages sent to a remote node at once
+    pub max_message_size: usize,
+    /// Transport used for all addresses, instead of the one chosen by [NodeAddr::transport]
+    pub transport: Option<Arc<dyn Transport>>,
+    /// Nodes dialed on startup to join the cluster. Attempts are retried until one of them succeeds,
+    /// the rest of the cluster is then discovered through it
+    pub seeds: Vec<NodeAddr>,
+    /// File listing additional seed nodes, see [parse_seeds] for the format
+    pub seeds_file: Option<PathBuf>,
+    /// Read additional seed nodes from the [SEEDS_ENV] environment variable
+    pub seeds_from_env: bool,
+    /// Roles of the node, like `ingest` or `query`, other nodes can select nodes by role in [ListNodes]
+    pub roles: Vec<String>,
+    /// Additional information published to the cluster, like datacenter or build version
+    pub metadata: HashMap<String, String>,
+    /// Find other nodes of the cluster on the local network
+    pub discovery: Option<DiscoveryConfig>,
+    /// Hidden nodes are not announced to the rest of the cluster, and only link
+    /// with the nodes they connect to explicitly
+    pub hidden: bool,
 }
 
 impl Default for NodeConfig {
     fn default() -> Self {
         NodeConfig {
             id: Uuid::new_v4(),
-            listen: ([127, 0, 0, 1], 9090).into(),
+            name: None,
+            listen: vec![NodeAddr::Tcp(([127, 0, 0, 1], 9090).into())],
+            advertise: None,
+            reconnect: ReconnectConfig::default(),
+            request_timeout: Duration::from_secs(60),
+            heartbeat_interval: Duration::from_secs(1),
+            heartbeat_timeout: Duration::from_secs(5),
+            handshake_timeout: Duration::from_secs(10),
+            tls: None,
+            cookie: None,
+            compression: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            chunk_size: 64 * 1024,
+            max_message_size: 256 * 1024 * 1024,
+            transport: None,
+            seeds: vec![],
+            seeds_file: None,
+            seeds_from_env: false,
+            roles: vec![],
+            metadata: HashMap::new(),
+            discovery: None,
+            hidden: false,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Transport used to listen on, or dial `addr`
+    pub fn transport(&self, addr: &NodeAddr) -> &dyn Transport {
+        match self.transport {
+            Some(ref transport) => &**transport,
+            None => addr.transport(),
+        }
+    }
+
+    /// Address other nodes should use to connect to this node
+    pub fn advertised(&self) -> Option<&NodeAddr> {
+        self.advertise.as_ref().or_else(|| self.listen.first())
+    }
+
+    /// Roles and metadata published to other nodes
+    pub(crate) fn local_metadata(&self) -> crate::proto::Metadata {
+        crate::proto::Metadata {
+            roles: self.roles.clone(),
+            values: self.metadata.clone(),
         }
     }
 }
@@ -28,14 +156,41 @@ impl Default for NodeConfig {
 pub struct NodeId(pub Uuid);
 
 impl NodeId {
+    /// Find a node of the cluster by its [name](NodeConfig::name)
+    pub fn by_name(name: &str) -> impl Future<Output=Result<NodeId, DispatchError>> {
+        let name = name.to_string();
+        // Resolved by the node whose scope the future runs in
+        async move {
+            let resolve = Node::current().controller().send(ResolveName(name));
+            let id = resolve.await.map_err(|_| DispatchError::MailboxRemote)?;
+            id.map(NodeId).ok_or(DispatchError::NodeNotFound)
+        }
+    }
+
     pub fn send<M, T>(&self, m: M) -> impl Future<Output=M::Result>
     where M: RpcMethod + Message<Result=Result<T, DispatchError>>
+    {
+        self.call(m, None)
+    }
+
+    /// Send a message, failing with [DispatchError::Timeout] if the node does not respond in time
+    pub fn send_timeout<M, T>(&self, m: M, timeout: Duration) -> impl Future<Output=M::Result>
+    where M: RpcMethod + Message<Result=Result<T, DispatchError>>
+    {
+        self.call(m, Some(timeout))
+    }
+
+    fn call<M, T>(&self, m: M, timeout: Option<Duration>) -> impl Future<Output=M::Result>
+    where M: RpcMethod + Message<Result=Result<T, DispatchError>>
     {
         let nodeid = self.0;
+        let node = Node::current();
+        let mut inner = m.make_call(None);
+        inner.timeout = timeout;
         async move {
-            let res = NodeController::from_registry().send(NodeDispatch {
+            let res = node.controller().send(NodeDispatch {
                 nodeid,
-                inner: m.make_call(None),
+                inner,
             }).await.map_err(|e| DispatchError::MailboxRemote)??;
 
             M::read_result(res)
@@ -44,7 +199,7 @@ impl NodeId {
     pub fn do_send<M>(&self, m: M)
     where M: RpcMethod
     {
-        NodeController::from_registry().do_send(NodeDispatch {
+        Node::current().controller().do_send(NodeDispatch {
             nodeid: self.0,
             inner: m.make_broadcast(None),
         })
@@ -52,35 +207,55 @@ impl NodeId {
 }
 
 pub struct NodeController {
+    node: Node,
+    /// Controller of the default node, configured through [Global<NodeConfig>]
+    system: bool,
+    config: NodeConfig,
     /// Links to other nodes
     links: HashMap<Uuid, Addr<NodeLink>>,
+    /// Linked nodes, as they introduced themselves during handshake
+    peers: HashMap<Uuid, Peer>,
+    /// Addresses of nodes we dialed, used to re-establish dropped links
+    dialed: HashMap<Uuid, NodeAddr>,
+    /// Attempts in a row dialed nodes rejected our name, while another node held it
+    name_clashes: HashMap<Uuid, u32>,
+    /// Nodes learned about from peers, which we are connecting to
+    connecting: HashMap<Uuid, SpawnHandle>,
+    /// Pending attempts to re-establish dropped links
+    reconnecting: HashMap<Uuid, SpawnHandle>,
+    /// Pending attempts to join the cluster through seed nodes
+    seeding: Vec<SpawnHandle>,
     /// Dispatcher for unaddressed messages.
     ///
     /// Messages which are sent to process id of 00000000000000000....
     /// are considered unadressed, and are dispatched from here
     pub dispatch: HashMap<u32, Box<dyn CallHandler>>,
     pub status_listeners: HashMap<Uuid, Recipient<NodeStatus>>,
+    /// Watchers of individual nodes, by node id and monitor id
+    monitors: HashMap<Uuid, HashMap<Uuid, Recipient<NodeDown>>>,
+    /// Listener and discovery tasks, stopped on shutdown
+    background: Vec<SpawnHandle>,
+    /// Node is leaving the cluster, no new links are established
+    shutting_down: bool,
+    /// First listen address which could not be bound
+    listen_error: Option<ListenError>,
+    /// Loaded [NodeConfig::tls], shared by all links
+    tls: Option<Arc<Tls>>,
 }
 
 impl SystemService for NodeController {}
 
 impl Supervised for NodeController {
     fn restarting(&mut self, ctx: &mut Self::Context) {
-        // Ensure process registry is initialized
-        ProcessRegistry::from_registry();
+        if !self.system {
+            return self.listen(ctx);
+        }
 
         let cfg = Global::<NodeConfig>::from_registry().send(Get::default());
         let set_cfg = wrap_future(cfg)
-            .then(|cfg, this: &mut Self, ctx| {
-                let cfg = cfg.unwrap();
-                log::warn!("Starting node listener on: {:?}", cfg);
-                let cfg = cfg.unwrap();
-
-                wrap_future(tokio::net::TcpListener::bind(cfg.listen))
-            })
-            .map(|listener, this: &mut Self, ctx| {
-                let mut listener = listener.unwrap();
-                ctx.add_stream(listener);
+            .map(|cfg, this: &mut Self, ctx| {
+                this.config = cfg.unwrap().unwrap();
+                this.listen(ctx);
             });
 
         ctx.wait(set_cfg);
@@ -90,9 +265,23 @@ impl Supervised for NodeController {
 impl Default for NodeController {
     fn default() -> Self {
         NodeController {
+            node: Node::system(),
+            system: true,
+            config: NodeConfig::default(),
             links: HashMap::new(),
+            peers: HashMap::new(),
+            dialed: HashMap::new(),
+            name_clashes: HashMap::new(),
+            connecting: HashMap::new(),
+            reconnecting: HashMap::new(),
+            seeding: vec![],
             dispatch: HashMap::new(),
             status_listeners: HashMap::new(),
+            monitors: HashMap::new(),
+            background: vec![],
+            shutting_down: false,
+            listen_error: None,
+            tls: None,
         }
     }
 }
@@ -105,18 +294,281 @@ impl Actor for NodeController {
     }
 }
 
-impl StreamHandler<std::io::Result<TcpStream>> for NodeController {
-    fn handle(&mut self, item: std::io::Result<TcpStream>, ctx: &mut Context<Self>) {
-        let item = item.expect("Fatal error in NodeControl");
+impl NodeController {
+    /// Controller of a node started with [Node::start]
+    pub(crate) fn new(node: Node, config: NodeConfig) -> Self {
+        NodeController {
+            node,
+            system: false,
+            config,
+            links: HashMap::new(),
+            peers: HashMap::new(),
+            dialed: HashMap::new(),
+            name_clashes: HashMap::new(),
+            connecting: HashMap::new(),
+            reconnecting: HashMap::new(),
+            seeding: vec![],
+            dispatch: HashMap::new(),
+            status_listeners: HashMap::new(),
+            monitors: HashMap::new(),
+            background: vec![],
+            shutting_down: false,
+            listen_error: None,
+            tls: None,
+        }
+    }
 
-        let link = wrap_future(NodeLink::new(item));
+    fn listen(&mut self, ctx: &mut Context<Self>) {
+        log::warn!("Starting node listener on: {:?}", self.config);
+        self.listen_error = None;
+        // Certificates are loaded once, a node which can't secure its links does not join the cluster
+        self.tls = match self.config.tls.as_ref().map(Tls::load).transpose() {
+            Ok(tls) => tls.map(Arc::new),
+            Err(e) => {
+                let e = ListenError::new(None, e);
+                log::error!("{}", e);
+                self.listen_error = Some(e);
+                return;
+            }
+        };
+        let binds = self.config.listen.iter()
+            .map(|addr| {
+                let addr = addr.clone();
+                self.config.transport(&addr).listen(&addr)
+                    .map_err(move |e| ListenError::new(Some(addr), e))
+            })
+            .collect::<Vec<_>>();
+        let listen = wrap_future(futures::future::join_all(binds))
+            .map(|results: Vec<Result<(Listener, NodeAddr), ListenError>>, this: &mut Self, ctx| {
+                let mut bound = Vec::with_capacity(results.len());
+                for res in results {
+                    match res {
+                        Ok((listener, addr)) => {
+                            log::info!("Node listening on {}", addr);
+                            let handle = ctx.add_stream(listener);
+                            this.background.push(handle);
+                            bound.push(addr);
+                        }
+                        Err(e) => {
+                            log::error!("{}", e);
+                            this.listen_error.get_or_insert(e);
+                        }
+                    }
+                }
+                // Addresses with port 0 are replaced by the ports actually bound
+                this.config.listen = bound;
+                if this.listen_error.is_none() {
+                    this.join_seeds(ctx);
+                    this.start_discovery(ctx);
+                }
+            });
+        ctx.wait(listen);
+    }
+
+    /// Register newly established link, introduce the node to the cluster and notify listeners.
+    ///
+    /// If we are already linked with the node, only one of the links is kept and returned.
+    /// Links to nodes using a name of another node are closed, unless the node listens on the same address,
+    /// in which case it is a restarted instance of that node and replaces it
+    fn link_up(&mut self, peer: Peer, link: Addr<NodeLink>, ctx: &mut Context<Self>) -> Result<Addr<NodeLink>, LinkError> {
+        // Handshakes and connection attempts which were in flight when shutdown started
+        if self.shutting_down {
+            link.do_send(Close(DisconnectReason::Shutdown));
+            return Err(LinkError::ShuttingDown);
+        }
+        let id = peer.id;
+        if let Some(ref name) = peer.name {
+            let clash = self.peers.values().find(|p| p.id != id && p.name.as_ref() == Some(name));
+            if let Some(old) = clash.cloned() {
+                if old.listen.is_none() || old.listen != peer.listen {
+                    link.do_send(Close(DisconnectReason::NameTaken));
+                    return Err(LinkError::NameTaken(name.clone()));
+                }
+                log::info!("Node {} restarted as {}", old, peer);
+                if let Some(existing) = self.links.remove(&old.id) {
+                    existing.do_send(Close(DisconnectReason::Restarted));
+                }
+                if let Some(addr) = self.dialed.remove(&old.id) {
+                    self.dialed.insert(id, addr);
+                }
+                self.peer_down(old.id, DisconnectReason::Restarted, ctx);
+            }
+        }
+
+        if let Some(existing) = self.links.get(&id).cloned() {
+            if !self.replaces(&peer, &self.peers[&id]) {
+                log::info!("Closing duplicate link to: {}", peer);
+                link.do_send(Close(DisconnectReason::Duplicate));
+                return Ok(existing);
+            }
+            log::info!("Replacing link to: {}", peer);
+            existing.do_send(Close(DisconnectReason::Duplicate));
+            self.links.insert(id, link.clone());
+            self.peers.insert(id, peer);
+            return Ok(link);
+        }
+        if self.peers.contains_key(&id) {
+            // Remote node closed our link as a duplicate, and this is the one it kept
+            log::info!("Link to {} kept by the remote node", peer);
+            self.links.insert(id, link.clone());
+            self.peers.insert(id, peer);
+            return Ok(link);
+        }
+
+        log::info!("Connected to: {}", peer);
+        self.links.insert(id, link.clone());
+        self.peers.insert(id, peer.clone());
+        self.announce(&peer, &link);
+        self.notify_status(NodeStatus::Connected(id));
+        Ok(link)
+    }
+
+    /// Register link to a node we dialed at `addr`, so it is re-established when it drops
+    fn dialed_up(&mut self, peer: Peer, link: Addr<NodeLink>, addr: NodeAddr, ctx: &mut Context<Self>) -> Result<Addr<NodeLink>, LinkError> {
+        let id = peer.id;
+        let link = self.link_up(peer, link, ctx)?;
+        self.dialed.insert(id, addr);
+        Ok(link)
+    }
+
+    /// Whether `new` link to a node should replace the `old` one.
+    ///
+    /// Both nodes come to the same decision: link dialed by the node with lower id is kept,
+    /// and if both were dialed by the same node, the newer one
+    fn replaces(&self, new: &Peer, old: &Peer) -> bool {
+        let dialer = |p: &Peer| if p.outbound { self.config.id } else { p.id };
+        let preferred = self.config.id.min(new.id);
+        match (dialer(new) == preferred, dialer(old) == preferred) {
+            (true, false) => true,
+            (false, true) => false,
+            _ => new.seq > old.seq,
+        }
+    }
+
+    /// Forget a node we are no longer linked with, notify listeners and watchers, and reconnect if we dialed it
+    fn peer_down(&mut self, id: Uuid, reason: DisconnectReason, ctx: &mut Context<Self>) {
+        log::info!("Disconnected from: {:?}, reason: {:?}", id, reason);
+        self.peers.remove(&id);
+        self.notify_status(NodeStatus::Disconnected(id, reason.clone()));
+        self.node_down(id, &reason);
+        if reason != DisconnectReason::NameTaken {
+            self.name_clashes.remove(&id);
+        }
+        match reason {
+            DisconnectReason::Shutdown | DisconnectReason::Restarted => {
+                self.dialed.remove(&id);
+            }
+            _ if self.shutting_down => {}
+            // Previous instance of this node may still hold our name, until the remote node notices it is gone
+            DisconnectReason::NameTaken => if let Some(addr) = self.dialed.get(&id).cloned() {
+                let attempt = self.name_clashes.entry(id).or_insert(0);
+                *attempt += 1;
+                let attempt = *attempt;
+                if self.config.reconnect.gives_up(attempt) {
+                    log::error!("Giving up reconnecting to {}, our name is taken", id);
+                    self.dialed.remove(&id);
+                    self.name_clashes.remove(&id);
+                    self.notify_status(NodeStatus::ReconnectFailed(id));
+                } else {
+                    self.notify_status(NodeStatus::Reconnecting(id, attempt));
+                    self.reconnect(id, addr, attempt, ctx);
+                }
+            }
+            _ => if let Some(addr) = self.dialed.get(&id).cloned() {
+                self.reconnect(id, addr, 0, ctx);
+            }
+        }
+    }
+
+    /// Tell listeners about a change of the cluster, forgetting listeners which stopped
+    fn notify_status(&mut self, status: NodeStatus) {
+        self.status_listeners.retain(|_, l| l.do_send(status.clone()).is_ok());
+    }
+
+    /// Re-establish link to a previously dialed node, backing off between attempts.
+    ///
+    /// `attempt` counts attempts which failed so far, listeners are told about every failure
+    fn reconnect(&mut self, id: Uuid, addr: NodeAddr, attempt: u32, ctx: &mut Context<Self>) {
+        let node = self.node.clone();
+        let cfg = self.config.clone();
+        let tls = self.tls.clone();
+        let delay = cfg.reconnect.delay(attempt);
+        log::info!("Reconnecting to {} at {} in {:?}", id, addr, delay);
+
+        let dial = addr.clone();
+        let conn = async move {
+            tokio::time::delay_for(delay).await;
+            reconnect::dial(node, cfg, tls, dial).await
+        };
+
+        let fut = wrap_future(conn).map(move |res, this: &mut Self, ctx| {
+            this.reconnecting.remove(&id);
+            match res {
+                Ok((peer, link)) => {
+                    if peer.id != id {
+                        // Node restarted with a new id, the old one is gone for good
+                        log::warn!("Node at {} changed id from {} to {}", addr, id, peer.id);
+                        this.dialed.remove(&id);
+                        this.notify_status(NodeStatus::ReconnectFailed(id));
+                    }
+                    match this.dialed_up(peer, link, addr.clone(), ctx) {
+                        Ok(_) => {}
+                        // Another node may hold the name only until it times out
+                        Err(e @ LinkError::NameTaken(_)) if !this.config.reconnect.gives_up(attempt + 1) => {
+                            log::warn!("Reconnecting to {} failed: {}", id, e);
+                            this.notify_status(NodeStatus::Reconnecting(id, attempt + 1));
+                            this.reconnect(id, addr, attempt + 1, ctx);
+                        }
+                        Err(e) => {
+                            log::error!("Giving up reconnecting to {}: {}", id, e);
+                            this.dialed.remove(&id);
+                            this.notify_status(NodeStatus::ReconnectFailed(id));
+                        }
+                    }
+                }
+                // Node linked with us in the meantime, or we are not interested anymore
+                Err(_) if this.shutting_down || this.links.contains_key(&id) || !this.dialed.contains_key(&id) => {}
+                Err(e) if reconnect::retryable(&e) && !this.config.reconnect.gives_up(attempt + 1) => {
+                    log::warn!("Reconnecting to {} failed: {}", id, e);
+                    this.notify_status(NodeStatus::Reconnecting(id, attempt + 1));
+                    this.reconnect(id, addr, attempt + 1, ctx);
+                }
+                Err(e) => {
+                    log::error!("Giving up reconnecting to {} after {} attempts: {}", id, attempt + 1, e);
+                    this.dialed.remove(&id);
+                    this.notify_status(NodeStatus::ReconnectFailed(id));
+                }
+            }
+        });
+        let handle = ctx.spawn(fut);
+        // At most one pending attempt per node, a newer one supersedes it
+        if let Some(previous) = self.reconnecting.insert(id, handle) {
+            ctx.cancel_future(previous);
+        }
+    }
+}
+
+impl StreamHandler<std::io::Result<(BoxConnection, NodeAddr)>> for NodeController {
+    fn handle(&mut self, item: std::io::Result<(BoxConnection, NodeAddr)>, ctx: &mut Context<Self>) {
+        let (item, peer) = match item {
+            Ok(item) => item,
+            Err(e) => {
+                log::error!("Accepting connection failed: {}", e);
+                return;
+            }
+        };
+
+        let link = wrap_future(NodeLink::new(self.node.clone(), self.config.clone(), self.tls.clone(), item, peer, false));
         let fut = link
-            .map(|(id, peer, link), this: &mut Self, ctx| {
-                log::info!("Connected to: {:?}", id);
-                this.links.insert(id, link.clone());
-                this.status_listeners.retain(|_, l| {
-                    l.do_send(NodeStatus::Connected(id)).is_ok()
-                });
+            .map(|res, this: &mut Self, ctx| {
+                match res {
+                    Ok((peer, link)) => {
+                        if let Err(e) = this.link_up(peer, link, ctx) {
+                            log::warn!("Rejected incoming connection: {}", e);
+                        }
+                    }
+                    Err(e) => log::warn!("Rejected incoming connection: {}", e),
+                }
             });
         ctx.spawn(fut);
     }
@@ -125,21 +577,55 @@ impl StreamHandler<std::io::Result<TcpStream>> for NodeController {
 #[derive(Debug, Clone)]
 pub enum NodeStatus {
     Connected(Uuid),
-    Disconnected(Uuid),
+    Disconnected(Uuid, DisconnectReason),
+    /// Re-establishing a dropped link failed the given number of times, and will be retried
+    Reconnecting(Uuid, u32),
+    /// Dropped link could not be re-established, and we gave up
+    ReconnectFailed(Uuid),
+    /// Linking with the node at this address failed the given number of times, and will be retried
+    Connecting(NodeAddr, u32),
+    /// Could not link with the node at this address, and we gave up
+    ConnectFailed(NodeAddr),
 }
 
 impl Message for NodeStatus { type Result = (); }
 
-impl Handler<NodeStatus> for NodeController {
+/// Status change noticed outside of the controller, passed on to listeners
+pub(crate) struct NotifyStatus(pub NodeStatus);
+
+impl Message for NotifyStatus { type Result = (); }
+
+impl Handler<NotifyStatus> for NodeController {
     type Result = ();
 
-    fn handle(&mut self, msg: NodeStatus, ctx: &mut Self::Context) -> Self::Result {
-        match msg {
-            NodeStatus::Disconnected(id) => {
-                self.status_listeners.retain(|_, l| l.do_send(msg.clone()).is_ok());
-            }
-            _ => panic!("Should not receive connected event yet")
+    fn handle(&mut self, msg: NotifyStatus, ctx: &mut Self::Context) -> Self::Result {
+        self.notify_status(msg.0);
+    }
+}
+
+impl Handler<LinkDown> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: LinkDown, ctx: &mut Self::Context) -> Self::Result {
+        let LinkDown { id, link, reason } = msg;
+        // Link replaced by another one to the same node
+        if self.links.get(&id) != Some(&link) {
+            return;
         }
+
+        self.links.remove(&id);
+        if reason == DisconnectReason::Duplicate {
+            // Remote node kept another link, which is about to be registered. The node did not leave,
+            // unless the other link fails to show up in time
+            log::info!("Link to {:?} closed as duplicate", id);
+            ctx.run_later(self.config.heartbeat_timeout, move |this, ctx| {
+                if this.peers.contains_key(&id) && !this.links.contains_key(&id) {
+                    this.peer_down(id, DisconnectReason::Duplicate, ctx);
+                }
+            });
+            return;
+        }
+        self.peer_down(id, reason, ctx);
     }
 }
 
@@ -153,47 +639,188 @@ impl Handler<RegisterRecipient<NodeStatus>> for NodeController {
     }
 }
 
+impl Handler<UnregisterRecipient> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnregisterRecipient, ctx: &mut Self::Context) -> Self::Result {
+        self.status_listeners.remove(&msg.0);
+    }
+}
+
+
+/// Leave the cluster gracefully.
+///
+/// Stops accepting connections, tells linked nodes we are leaving so they stop sending requests
+/// and forget our processes, and waits up to `drain` for requests in flight to finish.
+/// Links are closed afterwards
+pub struct Shutdown {
+    pub drain: Duration,
+}
+
+impl Message for Shutdown {
+    type Result = ();
+}
+
+impl Handler<Shutdown> for NodeController {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(&mut self, msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        log::info!("Shutting down node, draining links for up to {:?}", msg.drain);
+        self.shutting_down = true;
+        let pending = self.connecting.drain().chain(self.reconnecting.drain()).map(|(_, handle)| handle);
+        for handle in self.background.drain(..).chain(self.seeding.drain(..)).chain(pending) {
+            ctx.cancel_future(handle);
+        }
+
+        let links: Vec<_> = self.links.values().cloned().collect();
+        let drained = futures::future::join_all(links.iter().map(|l| l.send(Drain)));
+        let drained = tokio::time::timeout(msg.drain, drained);
+
+        Box::pin(wrap_future(drained).map(move |res, this: &mut Self, ctx| {
+            if res.is_err() {
+                log::warn!("Requests still in flight after drain timeout, closing links");
+            }
+            this.dialed.clear();
+            for link in links {
+                link.do_send(Close(DisconnectReason::Shutdown));
+            }
+        }))
+    }
+}
 
+/// Connect to a remote node.
+///
+/// Failed attempts are retried according to [NodeConfig::reconnect], up to
+/// [ReconnectConfig::connect_attempts]. Once linked, the link is re-established automatically when it drops.
 pub struct Connect {
-    pub addr: SocketAddr
+    pub addr: NodeAddr
 }
 
 impl Message for Connect {
-    type Result = Addr<NodeLink>;
+    type Result = Result<Addr<NodeLink>, LinkError>;
 }
 
 impl Handler<Connect> for NodeController {
-    type Result = ResponseActFuture<Self, Addr<NodeLink>>;
+    type Result = ResponseActFuture<Self, Result<Addr<NodeLink>, LinkError>>;
 
     fn handle(&mut self, msg: Connect, ctx: &mut Self::Context) -> Self::Result {
-        log::warn!("Connecting to remote node");
+        log::warn!("Connecting to remote node: {}", msg.addr);
 
-        // TODO: Retrying
-        let conn = async move {
-            let stream = TcpStream::connect(msg.addr).await.unwrap();
-            NodeLink::new(stream).await
-        };
+        let addr = msg.addr;
+        // Caller is waiting for the result, it gets an answer even if reconnects are retried forever
+        let cfg = &self.config.reconnect;
+        let attempts = cfg.max_attempts.map_or(cfg.connect_attempts, |max| max.min(cfg.connect_attempts));
+        let conn = reconnect::connect(self.node.clone(), self.config.clone(), self.tls.clone(), addr.clone(), Some(attempts));
 
         let link = wrap_future(conn);
-        Box::pin(link.map(|(id, peer, link), this: &mut Self, ctx| {
-            log::info!("Connected to: {:?}", id);
-            this.links.insert(id, link.clone());
-            link
+        Box::pin(link.map(move |res, this: &mut Self, ctx| {
+            let (peer, link) = res?;
+            this.dialed_up(peer, link, addr, ctx)
         }))
     }
 }
 
-pub struct ListNodes;
+/// Find id of a linked node, or this node, by its name
+pub struct ResolveName(pub String);
+
+impl Message for ResolveName {
+    type Result = Option<Uuid>;
+}
+
+impl Handler<ResolveName> for NodeController {
+    type Result = Option<Uuid>;
+
+    fn handle(&mut self, msg: ResolveName, ctx: &mut Context<Self>) -> Self::Result {
+        if self.config.name.as_ref() == Some(&msg.0) {
+            return Some(self.config.id);
+        }
+        self.peers.values().find(|p| p.name.as_ref() == Some(&msg.0)).map(|p| p.id)
+    }
+}
+
+/// List linked nodes, optionally only those with given role
+#[derive(Debug, Clone, Default)]
+pub struct ListNodes {
+    pub role: Option<String>,
+}
 
 impl Message for ListNodes {
-    type Result = Vec<Uuid>;
+    type Result = Vec<NodeInfo>;
 }
 
 impl Handler<ListNodes> for NodeController {
-    type Result = ResponseFuture<Vec<Uuid>>;
+    type Result = actix::MessageResult<ListNodes>;
 
     fn handle(&mut self, msg: ListNodes, ctx: &mut Context<Self>) -> Self::Result {
-        return Box::pin(futures::future::ready(self.links.keys().cloned().collect()));
+        let nodes = self.peers.values()
+            .map(NodeInfo::from)
+            .filter(|n| msg.role.as_ref().map_or(true, |role| n.has_role(role)))
+            .collect();
+        actix::MessageResult(nodes)
+    }
+}
+
+/// Binding one of the [listen](NodeConfig::listen) addresses failed, or the node could not start listening at all
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenError {
+    /// Address which could not be bound, `None` if [TLS](NodeConfig::tls) configuration could not be loaded
+    pub addr: Option<NodeAddr>,
+    pub kind: std::io::ErrorKind,
+    pub message: String,
+}
+
+impl ListenError {
+    fn new(addr: Option<NodeAddr>, err: std::io::Error) -> Self {
+        ListenError { addr, kind: err.kind(), message: err.to_string() }
+    }
+}
+
+impl std::fmt::Display for ListenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.addr {
+            Some(ref addr) => write!(f, "Listening on {} failed: {}", addr, self.message),
+            None => write!(f, "Loading TLS configuration failed: {}", self.message),
+        }
+    }
+}
+
+/// Addresses the node accepts connections on, with the actual ports of those bound to port 0.
+///
+/// Fails if any of the configured addresses could not be bound
+pub struct ListenAddrs;
+
+impl Message for ListenAddrs {
+    type Result = Result<Vec<NodeAddr>, ListenError>;
+}
+
+impl Handler<ListenAddrs> for NodeController {
+    type Result = Result<Vec<NodeAddr>, ListenError>;
+
+    fn handle(&mut self, msg: ListenAddrs, ctx: &mut Context<Self>) -> Self::Result {
+        match self.listen_error {
+            Some(ref e) => Err(e.clone()),
+            None => Ok(self.config.listen.clone()),
+        }
+    }
+}
+
+/// Query health statistics of links to all connected nodes
+pub struct GetLinkStats;
+
+impl Message for GetLinkStats {
+    type Result = HashMap<Uuid, LinkStats>;
+}
+
+impl Handler<GetLinkStats> for NodeController {
+    type Result = ResponseFuture<HashMap<Uuid, LinkStats>>;
+
+    fn handle(&mut self, msg: GetLinkStats, ctx: &mut Context<Self>) -> Self::Result {
+        let stats: Vec<_> = self.links.iter().map(|(id, link)| {
+            let id = *id;
+            link.send(ReadStats).map(move |r| r.ok().map(|s| (id, s)))
+        }).collect();
+
+        Box::pin(futures::future::join_all(stats).map(|s| s.into_iter().flatten().collect()))
     }
 }
 
@@ -207,7 +834,7 @@ impl Handler<NodeDispatch<MethodCall>> for NodeController {
 
             let send = link.send(msg.inner)
                 .map(|r| r
-                    .map_err(|_| DispatchError::MailboxLocal)
+                    .map_err(|_| DispatchError::ConnectionLost)
                     .and_then(|r| r)
                 );
 
@@ -0,0 +1,84 @@
+use crate::import::*;
+
+use crate::node::{Node, NodeId, NodeController, DisconnectReason};
+use crate::process::DispatchError;
+
+/// Sent once to the watcher of a node, when the link to it drops
+#[derive(Debug, Clone)]
+pub struct NodeDown {
+    pub node: Uuid,
+    pub reason: DisconnectReason,
+}
+
+impl Message for NodeDown {
+    type Result = ();
+}
+
+/// Watch a node, `recipient` receives [NodeDown] when we lose the link to it.
+///
+/// If the node is not linked, the notification is sent right away. Returns id of the monitor
+pub struct MonitorNode {
+    pub node: Uuid,
+    pub recipient: Recipient<NodeDown>,
+}
+
+impl Message for MonitorNode {
+    type Result = Uuid;
+}
+
+/// Stop watching a node, no [NodeDown] is delivered afterwards
+pub struct DemonitorNode(pub Uuid);
+
+impl Message for DemonitorNode {
+    type Result = ();
+}
+
+impl NodeId {
+    /// Watch this node from [Node::current], see [MonitorNode]
+    pub fn monitor(&self, recipient: Recipient<NodeDown>) -> impl Future<Output=Result<Uuid, DispatchError>> {
+        let msg = MonitorNode { node: self.0, recipient };
+        async move {
+            Node::current().controller().send(msg).await
+                .map_err(|_| DispatchError::MailboxRemote)
+        }
+    }
+
+    /// Remove monitor created with [NodeId::monitor]
+    pub fn demonitor(monitor: Uuid) {
+        Node::current().controller().do_send(DemonitorNode(monitor))
+    }
+}
+
+impl NodeController {
+    /// Notify and remove all watchers of a node
+    pub(super) fn node_down(&mut self, node: Uuid, reason: &DisconnectReason) {
+        for (_, watcher) in self.monitors.remove(&node).unwrap_or_default() {
+            let _ = watcher.do_send(NodeDown { node, reason: reason.clone() });
+        }
+    }
+}
+
+impl Handler<MonitorNode> for NodeController {
+    type Result = actix::MessageResult<MonitorNode>;
+
+    fn handle(&mut self, msg: MonitorNode, ctx: &mut Context<Self>) -> Self::Result {
+        let id = Uuid::new_v4();
+        if !self.peers.contains_key(&msg.node) {
+            let _ = msg.recipient.do_send(NodeDown { node: msg.node, reason: DisconnectReason::NotLinked });
+            return actix::MessageResult(id);
+        }
+        self.monitors.entry(msg.node).or_default().insert(id, msg.recipient);
+        actix::MessageResult(id)
+    }
+}
+
+impl Handler<DemonitorNode> for NodeController {
+    type Result = ();
+
+    fn handle(&mut self, msg: DemonitorNode, ctx: &mut Context<Self>) {
+        for watchers in self.monitors.values_mut() {
+            watchers.remove(&msg.0);
+        }
+        self.monitors.retain(|_, watchers| !watchers.is_empty());
+    }
+}
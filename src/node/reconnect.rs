@@ -0,0 +1,103 @@
+use crate::import::*;
+
+use rand::Rng;
+use crate::node::link::{NodeLink, LinkError, Peer};
+use crate::node::transport::NodeAddr;
+use crate::node::tls::Tls;
+use crate::node::{Node, NodeConfig, NodeStatus, NotifyStatus};
+
+/// Backoff settings used when dialing remote nodes
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Delay before the first retry
+    pub initial: Duration,
+    /// Upper bound for the delay between two attempts
+    pub max: Duration,
+    /// Factor by which the delay grows after every failed attempt
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, in range `0.0..=1.0`
+    pub jitter: f64,
+    /// Give up after this many failed attempts, `None` retries forever
+    pub max_attempts: Option<u32>,
+    /// Explicit [Connect](super::Connect) reports failure after at most this many attempts,
+    /// even if `max_attempts` allows more
+    pub connect_attempts: u32,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        ReconnectConfig {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+            connect_attempts: 5,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Delay to wait after `attempt` failed attempts (counted from 0)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.initial.as_secs_f64() * self.multiplier.powi(attempt.min(64) as i32);
+        let base = base.min(self.max.as_secs_f64());
+
+        let jitter = base * self.jitter.max(0.0).min(1.0);
+        let delay = base - jitter + rand::thread_rng().gen::<f64>() * 2.0 * jitter;
+
+        Duration::from_secs_f64(delay.max(0.0))
+    }
+
+    /// Whether to stop trying after `failed` attempts
+    pub fn gives_up(&self, failed: u32) -> bool {
+        self.max_attempts.map_or(false, |max| failed >= max)
+    }
+}
+
+/// Make a single attempt to establish a link to `addr`
+pub(crate) async fn dial(node: Node, config: NodeConfig, tls: Option<Arc<Tls>>, addr: NodeAddr) -> Result<(Peer, Addr<NodeLink>), LinkError> {
+    match config.transport(&addr).dial(&addr).await {
+        Ok(stream) => NodeLink::new(node, config, tls, stream, addr, true).await,
+        Err(e) => Err(LinkError::Io(e)),
+    }
+}
+
+/// Whether a failed attempt to link with a node is worth repeating.
+///
+/// Connection errors, including connections dropped or stalled during the handshake, are transient
+pub(crate) fn retryable(e: &LinkError) -> bool {
+    matches!(e, LinkError::Io(_))
+}
+
+/// Establish a link to `addr`, retrying failed attempts according to [NodeConfig::reconnect],
+/// up to `max_attempts` instead of the configured limit if given.
+///
+/// Only [retryable] failures are retried, a peer rejecting the handshake is reported right away.
+/// Status listeners are told about every failed attempt, by address as the node id is not known yet
+pub(crate) async fn connect(node: Node, config: NodeConfig, tls: Option<Arc<Tls>>, addr: NodeAddr, max_attempts: Option<u32>) -> Result<(Peer, Addr<NodeLink>), LinkError> {
+    let cfg = ReconnectConfig { max_attempts: max_attempts.or(config.reconnect.max_attempts), ..config.reconnect.clone() };
+    let notify = |status| node.controller().do_send(NotifyStatus(status));
+    let mut attempt = 0;
+    loop {
+        match dial(node.clone(), config.clone(), tls.clone(), addr.clone()).await {
+            Err(e) if retryable(&e) => {
+                attempt += 1;
+                if cfg.gives_up(attempt) {
+                    log::error!("Connecting to {} failed after {} attempts: {}", addr, attempt, e);
+                    notify(NodeStatus::ConnectFailed(addr.clone()));
+                    return Err(e);
+                }
+                let delay = cfg.delay(attempt - 1);
+                log::warn!("Connecting to {} failed: {}, retrying in {:?}", addr, e, delay);
+                notify(NodeStatus::Connecting(addr.clone(), attempt));
+                tokio::time::delay_for(delay).await;
+            }
+            Err(e) => {
+                notify(NodeStatus::ConnectFailed(addr.clone()));
+                return Err(e);
+            }
+            res => return res,
+        }
+    }
+}
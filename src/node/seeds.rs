@@ -0,0 +1,75 @@
+use crate::import::*;
+
+use std::io;
+use crate::node::{NodeController, NodeConfig};
+use crate::node::transport::{NodeAddr, AddrParseError};
+use crate::node::reconnect;
+
+/// Environment variable listing seed nodes, read if [NodeConfig::seeds_from_env] is set
+pub const SEEDS_ENV: &str = "QUIX_SEEDS";
+
+/// Parse a list of addresses separated by commas or whitespace, `#` starts a comment until the end of line
+pub fn parse_seeds(s: &str) -> Result<Vec<NodeAddr>, AddrParseError> {
+    s.lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|addr| !addr.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// All seed nodes listed in the configuration, its seed file and environment
+pub fn resolve_seeds(config: &NodeConfig) -> io::Result<Vec<NodeAddr>> {
+    let invalid = |e: AddrParseError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let mut seeds = config.seeds.clone();
+    if let Some(ref path) = config.seeds_file {
+        seeds.extend(parse_seeds(&std::fs::read_to_string(path)?).map_err(invalid)?);
+    }
+    if config.seeds_from_env {
+        if let Ok(env) = std::env::var(SEEDS_ENV) {
+            seeds.extend(parse_seeds(&env).map_err(invalid)?);
+        }
+    }
+    // The same list is usually given to all nodes, including the seeds themselves
+    let mut seen = HashSet::new();
+    seeds.retain(|addr| {
+        !config.listen.contains(addr) && config.advertise.as_ref() != Some(addr) && seen.insert(addr.clone())
+    });
+    Ok(seeds)
+}
+
+impl NodeController {
+    /// Dial all seed nodes, until we join the cluster through one of them
+    pub(super) fn join_seeds(&mut self, ctx: &mut Context<Self>) {
+        let seeds = match resolve_seeds(&self.config) {
+            Ok(seeds) => seeds,
+            Err(e) => {
+                log::error!("Reading seed nodes failed: {}", e);
+                return;
+            }
+        };
+
+        for addr in seeds {
+            log::info!("Joining cluster through seed node {}", addr);
+            let conn = reconnect::connect(self.node.clone(), self.config.clone(), self.tls.clone(), addr.clone(), None);
+            let fut = wrap_future(conn).map(move |res, this: &mut Self, ctx| {
+                match res {
+                    Ok((peer, link)) => {
+                        if let Err(e) = this.dialed_up(peer, link, addr.clone(), ctx) {
+                            log::warn!("Joining through seed node {} failed: {}", addr, e);
+                            return;
+                        }
+                        // Remaining seeds are found through gossip, or are not running
+                        for handle in this.seeding.drain(..) {
+                            ctx.cancel_future(handle);
+                        }
+                    }
+                    Err(e) => log::warn!("Joining through seed node {} failed: {}", addr, e),
+                }
+            });
+            let handle = ctx.spawn(fut);
+            self.seeding.push(handle);
+        }
+    }
+}
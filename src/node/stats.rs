@@ -0,0 +1,109 @@
+use crate::import::*;
+
+use std::collections::VecDeque;
+use tokio::time::Instant;
+use crate::proto::Net;
+
+/// Number of round trip samples kept for each link
+const RTT_SAMPLES: usize = 256;
+
+/// Round trip times measured over ping/pong exchanges
+#[derive(Debug, Clone)]
+pub struct RttStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub p99: Duration,
+    /// Number of samples the values were computed from
+    pub samples: usize,
+}
+
+/// Health statistics of a link to a single node.
+///
+/// Byte counts are sizes of encoded frames, before compression
+#[derive(Debug, Clone)]
+pub struct LinkStats {
+    /// `None` until the first pong is received
+    pub rtt: Option<RttStats>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Requests still waiting for a response from the remote node
+    pub pending: usize,
+    /// Time since we last received a frame from the remote node
+    pub last_seen: Duration,
+}
+
+/// Counters updated by the link as frames flow through it
+#[derive(Debug, Default)]
+pub(crate) struct LinkCounters {
+    rtt: VecDeque<Duration>,
+    pings: VecDeque<Instant>,
+
+    bytes_sent: u64,
+    bytes_received: u64,
+    frames_sent: u64,
+    frames_received: u64,
+}
+
+fn frame_len(frame: &Net) -> u64 {
+    // Length prefix + body
+    4 + prost::Message::encoded_len(frame) as u64
+}
+
+impl LinkCounters {
+    pub(crate) fn sent(&mut self, frame: &Net) {
+        self.frames_sent += 1;
+        self.bytes_sent += frame_len(frame);
+        if frame.ping.is_some() {
+            // Pongs arrive in the order the pings were sent
+            if self.pings.len() == RTT_SAMPLES {
+                self.pings.pop_front();
+            }
+            self.pings.push_back(Instant::now());
+        }
+    }
+
+    pub(crate) fn received(&mut self, frame: &Net) {
+        self.frames_received += 1;
+        self.bytes_received += frame_len(frame);
+        if frame.pong.is_some() {
+            if let Some(sent) = self.pings.pop_front() {
+                if self.rtt.len() == RTT_SAMPLES {
+                    self.rtt.pop_front();
+                }
+                self.rtt.push_back(sent.elapsed());
+            }
+        }
+    }
+
+    fn rtt(&self) -> Option<RttStats> {
+        if self.rtt.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.rtt.iter().cloned().collect();
+        sorted.sort();
+
+        let total: Duration = sorted.iter().sum();
+        let p99 = (sorted.len() * 99 + 99) / 100 - 1;
+
+        Some(RttStats {
+            min: sorted[0],
+            avg: total / sorted.len() as u32,
+            p99: sorted[p99],
+            samples: sorted.len(),
+        })
+    }
+
+    pub(crate) fn stats(&self, pending: usize, last_seen: Instant) -> LinkStats {
+        LinkStats {
+            rtt: self.rtt(),
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            frames_sent: self.frames_sent,
+            frames_received: self.frames_received,
+            pending,
+            last_seen: last_seen.elapsed(),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::import::*;
+
+use std::io::{self, BufReader};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_rustls::rustls::{
+    self, Certificate, PrivateKey, RootCertStore, ClientConfig, ServerConfig,
+    AllowAnyAuthenticatedClient, ServerCertVerifier, ServerCertVerified, TLSError,
+};
+use tokio_rustls::webpki::{self, DNSNameRef};
+
+/// Name used for SNI when dialing, actual identity of the peer is checked against its node id
+pub(crate) const SERVER_NAME: &str = "node.quix";
+
+static SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// TLS settings for node links, loaded once when the node starts.
+///
+/// Both sides of a link present a certificate, which must be signed by one of the trusted CAs.
+/// Nodes act as TLS servers for connections they accept, and as clients for those they dial,
+/// so node certificates must allow both the `serverAuth` and `clientAuth` extended key usages
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain of this node
+    pub cert: PathBuf,
+    /// PEM encoded private key of this node, PKCS#8 or RSA
+    pub key: PathBuf,
+    /// PEM encoded bundle of CAs used to verify other nodes
+    pub ca: PathBuf,
+    /// Require certificates of other nodes to be issued for `<nodeid>.quix`,
+    /// tying them to the node id sent during the handshake
+    pub verify_node_id: bool,
+}
+
+/// Loaded TLS configuration, ready to wrap connections
+pub(crate) struct Tls {
+    pub(crate) connector: TlsConnector,
+    pub(crate) acceptor: TlsAcceptor,
+    pub(crate) verify_node_id: bool,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<Certificate>> {
+    let mut rd = BufReader::new(File::open(path)?);
+    rustls::internal::pemfile::certs(&mut rd)
+        .map_err(|_| invalid(format!("Invalid certificate file: {:?}", path)))
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKey> {
+    let mut rd = BufReader::new(File::open(path)?);
+    let mut keys = rustls::internal::pemfile::pkcs8_private_keys(&mut rd)
+        .map_err(|_| invalid(format!("Invalid key file: {:?}", path)))?;
+
+    if keys.is_empty() {
+        let mut rd = BufReader::new(File::open(path)?);
+        keys = rustls::internal::pemfile::rsa_private_keys(&mut rd)
+            .map_err(|_| invalid(format!("Invalid key file: {:?}", path)))?;
+    }
+    keys.pop().ok_or_else(|| invalid(format!("No private key found in: {:?}", path)))
+}
+
+fn load_roots(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let mut rd = BufReader::new(File::open(path)?);
+    let (valid, _) = roots.add_pem_file(&mut rd)
+        .map_err(|_| invalid(format!("Invalid CA file: {:?}", path)))?;
+
+    if valid == 0 {
+        return Err(invalid(format!("No CA certificates found in: {:?}", path)));
+    }
+    Ok(roots)
+}
+
+impl Tls {
+    pub(crate) fn load(cfg: &TlsConfig) -> io::Result<Self> {
+        let certs = load_certs(&cfg.cert)?;
+        let key = load_key(&cfg.key)?;
+        let roots = load_roots(&cfg.ca)?;
+
+        let mut server = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots.clone()));
+        server.set_single_cert(certs.clone(), key.clone())
+            .map_err(|e| invalid(format!("Invalid certificate or key: {}", e)))?;
+
+        let mut client = ClientConfig::new();
+        client.root_store = roots;
+        client.set_single_client_cert(certs, key)
+            .map_err(|e| invalid(format!("Invalid certificate or key: {}", e)))?;
+        client.dangerous().set_certificate_verifier(Arc::new(ChainVerifier));
+
+        Ok(Tls {
+            connector: TlsConnector::from(Arc::new(client)),
+            acceptor: TlsAcceptor::from(Arc::new(server)),
+            verify_node_id: cfg.verify_node_id,
+        })
+    }
+
+    pub(crate) fn server_name() -> DNSNameRef<'static> {
+        DNSNameRef::try_from_ascii_str(SERVER_NAME).unwrap()
+    }
+}
+
+/// Verifies the certificate chain of the remote node, but not its name.
+///
+/// We don't know which node we are dialing, so the name is checked against node id after the handshake
+struct ChainVerifier;
+
+impl ServerCertVerifier for ChainVerifier {
+    fn verify_server_cert(&self, roots: &RootCertStore, presented: &[Certificate], _: DNSNameRef, _: &[u8]) -> Result<ServerCertVerified, TLSError> {
+        let cert = presented.first().ok_or(TLSError::NoCertificatesPresented)?;
+        let cert = webpki::EndEntityCert::from(&cert.0).map_err(TLSError::WebPKIError)?;
+
+        let chain: Vec<&[u8]> = presented.iter().skip(1).map(|c| c.0.as_ref()).collect();
+        let anchors: Vec<webpki::TrustAnchor> = roots.roots.iter().map(|r| r.to_trust_anchor()).collect();
+        let now = webpki::Time::try_from(SystemTime::now())
+            .map_err(|_| TLSError::FailedToGetCurrentTime)?;
+
+        cert.verify_is_valid_tls_server_cert(SIG_ALGS, &webpki::TLSServerTrustAnchors(&anchors), &chain, now)
+            .map_err(TLSError::WebPKIError)?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Check whether the certificate presented by remote node was issued for its node id
+pub(crate) fn verify_node_id(certs: &[Certificate], id: Uuid) -> bool {
+    let cert = match certs.first().map(|c| webpki::EndEntityCert::from(&c.0)) {
+        Some(Ok(cert)) => cert,
+        _ => return false,
+    };
+    let name = format!("{}.quix", id);
+    match DNSNameRef::try_from_ascii_str(&name) {
+        Ok(name) => cert.verify_is_valid_for_dns_name(name).is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,353 @@
+use crate::import::*;
+
+use std::io;
+use std::fmt;
+use std::str::FromStr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use futures::channel::mpsc;
+use futures::stream::BoxStream;
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncRead, AsyncWrite};
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Address of a node, along with the transport used to reach it.
+///
+/// Parsed from `tcp://127.0.0.1:9090`, `unix:///run/quix.sock` or `mem://name`.
+/// Plain `ip:port` is accepted as a TCP address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NodeAddr {
+    Tcp(SocketAddr),
+    /// Unix domain socket, for nodes running on the same host
+    Unix(PathBuf),
+    /// In-process channel, for nodes embedded in the same binary
+    Memory(String),
+}
+
+impl NodeAddr {
+    /// Transport able to listen on and dial this address
+    pub fn transport(&self) -> &'static dyn Transport {
+        match self {
+            NodeAddr::Tcp(_) => &TcpTransport,
+            NodeAddr::Unix(_) => &UnixTransport,
+            NodeAddr::Memory(_) => &MemoryTransport,
+        }
+    }
+
+    /// Address to reach a node which announced `self`, and was seen at `seen`.
+    ///
+    /// Nodes listening on all interfaces announce an unspecified ip like `0.0.0.0`, which would
+    /// lead other nodes to their own host. The ip is taken from `seen` instead, keeping the announced port
+    pub fn seen_at(&self, seen: &NodeAddr) -> NodeAddr {
+        match (self, seen) {
+            (NodeAddr::Tcp(addr), NodeAddr::Tcp(seen)) if addr.ip().is_unspecified() => {
+                NodeAddr::Tcp(SocketAddr::new(seen.ip(), addr.port()))
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+impl From<SocketAddr> for NodeAddr {
+    fn from(addr: SocketAddr) -> Self {
+        NodeAddr::Tcp(addr)
+    }
+}
+
+impl fmt::Display for NodeAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeAddr::Tcp(addr) => write!(f, "tcp://{}", addr),
+            NodeAddr::Unix(path) => write!(f, "unix://{}", path.display()),
+            NodeAddr::Memory(name) => write!(f, "mem://{}", name),
+        }
+    }
+}
+
+/// Error parsing a [NodeAddr]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrParseError(String);
+
+impl fmt::Display for AddrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid node address: {}", self.0)
+    }
+}
+
+impl std::error::Error for AddrParseError {}
+
+impl FromStr for NodeAddr {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AddrParseError(s.to_string());
+
+        if let Some(addr) = s.strip_prefix("tcp://") {
+            addr.parse().map(NodeAddr::Tcp).map_err(|_| invalid())
+        } else if let Some(path) = s.strip_prefix("unix://") {
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            Ok(NodeAddr::Unix(PathBuf::from(path)))
+        } else if let Some(name) = s.strip_prefix("mem://") {
+            if name.is_empty() {
+                return Err(invalid());
+            }
+            Ok(NodeAddr::Memory(name.to_string()))
+        } else {
+            s.parse().map(NodeAddr::Tcp).map_err(|_| invalid())
+        }
+    }
+}
+
+/// Bidirectional byte stream to another node, the link protocol runs on top of it
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> Connection for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+pub type BoxConnection = Box<dyn Connection>;
+
+/// Stream of accepted connections, along with the addresses they came from.
+///
+/// Transports without meaningful peer addresses report the address of the listener
+pub type Listener = BoxStream<'static, io::Result<(BoxConnection, NodeAddr)>>;
+
+/// Way of establishing connections between nodes
+pub trait Transport: Send + Sync + fmt::Debug {
+    /// Start accepting connections on `addr`.
+    ///
+    /// Returns the listener along with the address it is bound to, which differs from `addr` when binding to port 0
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>>;
+    /// Open a connection to a node listening on `addr`
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>>;
+}
+
+fn unsupported(addr: &NodeAddr) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Address not supported by transport: {}", addr))
+}
+
+/// Bind listening socket. IPv6 sockets don't accept IPv4 connections,
+/// so a node can listen on the same port with both
+fn bind_tcp(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+    let domain = if addr.is_ipv6() { Domain::ipv6() } else { Domain::ipv4() };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into_tcp_listener())
+}
+
+#[derive(Debug)]
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>> {
+        let addr = match addr {
+            NodeAddr::Tcp(addr) => *addr,
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        Box::pin(async move {
+            let listener = tokio::net::TcpListener::from_std(bind_tcp(addr)?)?;
+            let local = NodeAddr::Tcp(listener.local_addr()?);
+            let listener: Listener = Box::pin(listener.map(|stream| {
+                let stream = stream?;
+                stream.set_nodelay(true)?;
+                let peer = NodeAddr::Tcp(stream.peer_addr()?);
+                Ok((Box::new(stream) as BoxConnection, peer))
+            }));
+            Ok((listener, local))
+        })
+    }
+
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>> {
+        let addr = match addr {
+            NodeAddr::Tcp(addr) => *addr,
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        Box::pin(async move {
+            let stream = tokio::net::TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream) as BoxConnection)
+        })
+    }
+}
+
+/// Unix domain sockets. Listening fails if the socket file already exists
+#[derive(Debug)]
+pub struct UnixTransport;
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>> {
+        let path = match addr {
+            NodeAddr::Unix(path) => path.clone(),
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        Box::pin(async move {
+            // Socket file left behind by a node which didn't stop cleanly would fail the bind.
+            // Only remove it if nobody answers on it anymore
+            if path.exists() {
+                match tokio::net::UnixStream::connect(&path).await {
+                    Ok(_) => {
+                        let msg = format!("Already listening on: {}", path.display());
+                        return Err(io::Error::new(io::ErrorKind::AddrInUse, msg));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(&path)?,
+                    Err(_) => {}
+                }
+            }
+            let listener = tokio::net::UnixListener::bind(&path)?;
+            let guard = UnlinkOnDrop(path.clone());
+            let local = NodeAddr::Unix(path);
+            let bound = local.clone();
+            let listener: Listener = Box::pin(listener.map(move |stream| {
+                let _ = &guard;
+                Ok((Box::new(stream?) as BoxConnection, local.clone()))
+            }));
+            Ok((listener, bound))
+        })
+    }
+
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>> {
+        let path = match addr {
+            NodeAddr::Unix(path) => path.clone(),
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        Box::pin(async move {
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            Ok(Box::new(stream) as BoxConnection)
+        })
+    }
+}
+
+/// Removes the socket file once its listener is dropped
+#[cfg(unix)]
+struct UnlinkOnDrop(PathBuf);
+
+#[cfg(unix)]
+impl Drop for UnlinkOnDrop {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(not(unix))]
+impl Transport for UnixTransport {
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>> {
+        Box::pin(futures::future::err(unsupported(addr)))
+    }
+
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>> {
+        Box::pin(futures::future::err(unsupported(addr)))
+    }
+}
+
+/// Listeners of the in-process transport, shared by all nodes in this process
+static MEMORY_LISTENERS: Lazy<Mutex<HashMap<String, mpsc::UnboundedSender<BoxConnection>>>> = Lazy::new(Default::default);
+
+/// In-process transport, connections are pairs of channels.
+///
+/// Writes are never blocked, so it should only be used between nodes which keep up with each other
+#[derive(Debug)]
+pub struct MemoryTransport;
+
+impl Transport for MemoryTransport {
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>> {
+        let name = match addr {
+            NodeAddr::Memory(name) => name.clone(),
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        let mut listeners = MEMORY_LISTENERS.lock().unwrap();
+        // Listener of a stopped node is replaced
+        if listeners.get(&name).map_or(false, |l| !l.is_closed()) {
+            let err = io::Error::new(io::ErrorKind::AddrInUse, format!("Already listening on: {}", addr));
+            return Box::pin(futures::future::err(err));
+        }
+        let (tx, rx) = mpsc::unbounded();
+        listeners.insert(name, tx);
+
+        let local = addr.clone();
+        let listener: Listener = Box::pin(rx.map(move |conn| Ok((conn, local.clone()))));
+        Box::pin(futures::future::ok((listener, addr.clone())))
+    }
+
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>> {
+        let name = match addr {
+            NodeAddr::Memory(name) => name,
+            other => return Box::pin(futures::future::err(unsupported(other))),
+        };
+        let mut listeners = MEMORY_LISTENERS.lock().unwrap();
+        let (local, remote) = MemoryStream::pair();
+
+        let accepted = match listeners.get(name) {
+            Some(listener) => listener.unbounded_send(Box::new(remote)).is_ok(),
+            None => false,
+        };
+        if !accepted {
+            listeners.remove(name);
+            let err = io::Error::new(io::ErrorKind::ConnectionRefused, format!("Nobody listening on: {}", addr));
+            return Box::pin(futures::future::err(err));
+        }
+        Box::pin(futures::future::ok(Box::new(local) as BoxConnection))
+    }
+}
+
+/// One end of an in-process connection
+pub struct MemoryStream {
+    tx: mpsc::UnboundedSender<Bytes>,
+    rx: mpsc::UnboundedReceiver<Bytes>,
+    /// Data received, but not yet read
+    buf: Bytes,
+}
+
+impl MemoryStream {
+    /// Create both ends of a connection
+    pub fn pair() -> (MemoryStream, MemoryStream) {
+        let (atx, arx) = mpsc::unbounded();
+        let (btx, brx) = mpsc::unbounded();
+        let a = MemoryStream { tx: atx, rx: brx, buf: Bytes::new() };
+        let b = MemoryStream { tx: btx, rx: arx, buf: Bytes::new() };
+        (a, b)
+    }
+}
+
+impl AsyncRead for MemoryStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.buf.is_empty() {
+            match futures::ready!(self.rx.poll_next_unpin(cx)) {
+                Some(data) => self.buf = data,
+                // Other side closed the connection
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let len = buf.len().min(self.buf.len());
+        buf[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.advance(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for MemoryStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        match self.tx.unbounded_send(Bytes::copy_from_slice(buf)) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        self.tx.close_channel();
+        Poll::Ready(Ok(()))
+    }
+}
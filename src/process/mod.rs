@@ -1,7 +1,7 @@
 use crate::import::*;
 
 use crate::process::registry::{ProcessRegistry, Register, Unregister};
-use crate::node::NodeController;
+use crate::node::Node;
 use crate::util::RpcMethod;
 
 use actix::dev::{ContextParts, Mailbox, ContextFut, AsyncContextParts, ToEnvelope, Envelope, RecipientRequest};
@@ -19,6 +19,10 @@ pub enum DispatchError {
     NodeNotFound = 3,
     MessageFormat,
     Timeout,
+    /// Link to the remote node was lost before the response arrived
+    ConnectionLost,
+    /// Request or response is larger than the link accepts, see [NodeConfig::max_message_size](crate::node::NodeConfig::max_message_size)
+    MessageTooLarge,
 
     MailboxRemote,
     MailboxLocal,
@@ -36,6 +40,8 @@ impl DispatchError {
             NodeNotFound => 3,
             MessageFormat => 4,
             Timeout => 5,
+            ConnectionLost => 6,
+            MessageTooLarge => 7,
             _ => 99
         }
     }
@@ -47,6 +53,8 @@ impl DispatchError {
             3 => NodeNotFound,
             4 => MessageFormat,
             5 => Timeout,
+            6 => ConnectionLost,
+            7 => MessageTooLarge,
             _ => Other
         }
     }
@@ -96,13 +104,22 @@ pub struct Process<A: Actor<Context=Self>>
 
 impl<A: Actor<Context=Self>> Process<A> where A: DynHandler
 {
-    /// Start a new process
+    /// Start a new process on the [current](Node::current) node
     pub fn start(a: A) -> Pid<A> {
         Self::start_with(|_| a)
     }
 
+    /// Start a new process on specified node
+    pub fn start_in(node: &Node, a: A) -> Pid<A> {
+        Self::create(node.clone(), |_| a)
+    }
+
     /// Start a new process, with the ability to manipiulate its context before  actual startup
     pub fn start_with(f: impl FnOnce(&mut Self) -> A) -> Pid<A> {
+        Self::create(Node::current(), f)
+    }
+
+    fn create(node: Node, f: impl FnOnce(&mut Self) -> A) -> Pid<A> {
         let (tx, rx) = actix::dev::channel::channel(8);
         // Global process registry
         let id = Uuid::new_v4();
@@ -114,7 +131,7 @@ impl<A: Actor<Context=Self>> Process<A> where A: DynHandler
         };
 
         let act = f(&mut proc);
-        proc.run(act)
+        proc.run(node, act)
     }
 
     /// Get [Pid] of current process
@@ -125,16 +142,18 @@ impl<A: Actor<Context=Self>> Process<A> where A: DynHandler
         };
     }
 
-    fn run(mut self, act: A) -> Pid<A> {
+    fn run(mut self, node: Node, act: A) -> Pid<A> {
         let id = self.id;
         let pid = self.pid();
         let fut = self.into_fut(act);
         // Register this process with registry when starting
-        ProcessRegistry::from_registry().do_send(Register::new(pid.clone()));
+        let registry = node.registry().clone();
+        registry.do_send(Register::new(pid.clone()));
         let fut = fut.map(move |_| {
-            ProcessRegistry::from_registry().do_send(Unregister { id });
+            registry.do_send(Unregister { id });
         });
-        actix_rt::spawn(fut);
+        // Messages sent by the process are routed through its node
+        actix_rt::spawn(node.scope(fut));
         pid
     }
 
@@ -254,7 +273,7 @@ impl<A: Actor + DynHandler> Pid<A> {
             Pid::Local { addr, .. } => PidRequest::Local(addr.send(m)),
             Pid::Remote(id) => {
                 let dispatch = m.make_call(Some(*id));
-                PidRequest::Remote(ProcessRegistry::from_registry().send(dispatch))
+                PidRequest::Remote(RemoteRequest::new(dispatch))
             }
         }
     }
@@ -269,7 +288,7 @@ impl<A: Actor + DynHandler> Pid<A> {
             Self::Local { addr, .. } => addr.do_send(m),
             Self::Remote(id) => {
                 let dispatch = m.make_broadcast(Some(*id));
-                ProcessRegistry::from_registry().do_send(dispatch)
+                Node::current().registry().do_send(dispatch)
             }
         }
     }
@@ -293,6 +312,56 @@ impl<A: Actor + DynHandler> Pid<A> {
     }
 }
 
+/// Call to a remote process.
+///
+/// The call is submitted to [ProcessRegistry] of the node it was created on when first polled,
+/// so that it can still be configured
+pub struct RemoteRequest {
+    registry: Addr<ProcessRegistry>,
+    call: Option<MethodCall>,
+    req: Option<Request<ProcessRegistry, MethodCall>>,
+}
+
+impl RemoteRequest {
+    pub(crate) fn new(call: MethodCall) -> Self {
+        Self {
+            registry: Node::current().registry().clone(),
+            call: Some(call),
+            req: None,
+        }
+    }
+
+    fn timeout(mut self, dur: Duration) -> Self {
+        if let Some(ref mut call) = self.call {
+            call.timeout = Some(dur);
+        }
+        self
+    }
+}
+
+impl Future for RemoteRequest {
+    type Output = Result<Bytes, DispatchError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(call) = this.call.take() {
+            this.req = Some(this.registry.send(call));
+        }
+
+        match futures::ready!(this.req.as_mut().unwrap().poll_unpin(cx)) {
+            Ok(res) => Poll::Ready(res),
+            Err(mailbox) => Poll::Ready(Err(DispatchError::MailboxLocal)),
+        }
+    }
+}
+
+fn mailbox_error(e: MailboxError) -> DispatchError {
+    match e {
+        MailboxError::Timeout => DispatchError::Timeout,
+        MailboxError::Closed => DispatchError::MailboxRemote,
+    }
+}
+
 /// Request to send message to remote process
 /// This can only be used to send addressed messages
 pub enum PidRequest<A, M>
@@ -302,7 +371,23 @@ where A: Actor + Handler<M>,
 
 {
     Local(Request<A, M>),
-    Remote(Request<ProcessRegistry, MethodCall>),
+    Remote(RemoteRequest),
+}
+
+impl<A, M> PidRequest<A, M>
+where A: Actor + Handler<M>,
+      A::Context: ToEnvelope<A, M>,
+      M: Message
+{
+    /// Fail with [DispatchError::Timeout] if the response does not arrive in `dur`.
+    ///
+    /// Remote calls carry the timeout over the wire, so the remote node can drop work nobody waits for.
+    pub fn timeout(self, dur: Duration) -> Self {
+        match self {
+            PidRequest::Local(r) => PidRequest::Local(r.timeout(dur)),
+            PidRequest::Remote(r) => PidRequest::Remote(r.timeout(dur)),
+        }
+    }
 }
 
 impl<A: Actor, M: Message> Future for PidRequest<A, M>
@@ -316,15 +401,14 @@ where A: Actor + Handler<M>,
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         match self.get_mut() {
             PidRequest::Local(r) => {
-                r.poll_unpin(cx).map_err(|e| DispatchError::MailboxRemote)
+                r.poll_unpin(cx).map_err(mailbox_error)
             }
             PidRequest::Remote(r) => {
                 match futures::ready!(r.poll_unpin(cx)) {
-                    Ok(Ok(res)) => {
+                    Ok(res) => {
                         Poll::Ready(Ok(<M as RpcMethod>::read_result(res)))
                     }
-                    Ok(Err(err)) => Poll::Ready(Err(err)),
-                    Err(mailbox) => Poll::Ready(Err(DispatchError::MailboxLocal)),
+                    Err(err) => Poll::Ready(Err(err)),
                 }
             }
         }
@@ -372,7 +456,7 @@ where M: Message + RpcMethod + Send,
             return PidRecipientRequest::Local(local.send(m));
         } else {
             let dispatch = m.make_call(Some(self.id));
-            PidRecipientRequest::Remote(ProcessRegistry::from_registry().send(dispatch))
+            PidRecipientRequest::Remote(RemoteRequest::new(dispatch))
         }
     }
 
@@ -381,7 +465,7 @@ where M: Message + RpcMethod + Send,
             local.do_send(m)
         } else {
             let dispatch = m.make_call(Some(self.id));
-            Ok(ProcessRegistry::from_registry().do_send(dispatch))
+            Ok(Node::current().registry().do_send(dispatch))
         }
     }
 }
@@ -390,7 +474,21 @@ pub enum PidRecipientRequest<M>
 where M: Message + Send + 'static,
       M::Result: Send {
     Local(RecipientRequest<M>),
-    Remote(Request<ProcessRegistry, MethodCall>),
+    Remote(RemoteRequest),
+}
+
+impl<M> PidRecipientRequest<M>
+where M: Message + Send + 'static,
+      M::Result: Send {
+    /// Fail with [DispatchError::Timeout] if the response does not arrive in `dur`.
+    ///
+    /// See [PidRequest::timeout]
+    pub fn timeout(self, dur: Duration) -> Self {
+        match self {
+            Self::Local(r) => Self::Local(r.timeout(dur)),
+            Self::Remote(r) => Self::Remote(r.timeout(dur)),
+        }
+    }
 }
 
 impl<M: Message> Future for PidRecipientRequest<M>
@@ -402,15 +500,17 @@ where M: Message + RpcMethod + Unpin + Send,
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         match self.get_mut() {
             Self::Local(r) => {
-                r.poll_unpin(cx).map_err(|e| DispatchError::MailboxLocal)
+                r.poll_unpin(cx).map_err(|e| match e {
+                    MailboxError::Timeout => DispatchError::Timeout,
+                    MailboxError::Closed => DispatchError::MailboxLocal,
+                })
             }
             Self::Remote(r) => {
                 match futures::ready!(r.poll_unpin(cx)) {
-                    Ok(Ok(res)) => {
+                    Ok(res) => {
                         Poll::Ready(Ok(<M as RpcMethod>::read_result(res)))
                     }
-                    Ok(Err(err)) => Poll::Ready(Err(err)),
-                    Err(mailbox) => Poll::Ready(Err(DispatchError::MailboxLocal)),
+                    Err(err) => Poll::Ready(Err(err)),
                 }
             }
         }
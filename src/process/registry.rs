@@ -2,14 +2,19 @@ use crate::import::*;
 
 
 use crate::process::{Dispatcher, DynHandler, Pid, Process, DispatchError};
-use crate::node::{NodeController, RegisterGlobalHandler, FromNode, NodeStatus};
+use crate::node::{Node, RegisterGlobalHandler, FromNode, NodeStatus, DisconnectReason};
 use crate::util::{RegisterRecipient, RpcMethod};
 use crate::proto::{Update, ProcessList};
 use crate::{NodeDispatch, MethodCall, Broadcast};
 
 pub struct ProcessRegistry {
+    node: Node,
     local: HashMap<Uuid, Box<dyn Dispatcher>>,
     nodes: HashMap<Uuid, Uuid>,
+    /// Processes hosted on nodes we lost connection to.
+    ///
+    /// Kept so that calls to them fail with [DispatchError::NodeNotFound], until the node reconnects
+    unreachable: HashMap<Uuid, Uuid>,
 
     new: HashSet<Uuid>,
     deleted: HashSet<Uuid>,
@@ -25,9 +30,17 @@ impl Actor for ProcessRegistry {
 
 impl Default for ProcessRegistry {
     fn default() -> Self {
+        Self::new(Node::system())
+    }
+}
+
+impl ProcessRegistry {
+    pub(crate) fn new(node: Node) -> Self {
         Self {
+            node,
             local: HashMap::new(),
             nodes: HashMap::new(),
+            unreachable: HashMap::new(),
 
             new: HashSet::new(),
             deleted: HashSet::new(),
@@ -45,7 +58,7 @@ fn fold_uuids(mut a: Vec<u8>, u: &Uuid) -> Vec<u8> {
 impl Supervised for ProcessRegistry {
     fn restarting(&mut self, ctx: &mut Self::Context) {
         log::info!("Setting up process registry");
-        let control = NodeController::from_registry();
+        let control = self.node.controller();
 
         control.do_send(RegisterRecipient(ctx.address().recipient::<NodeStatus>()));
         control.do_send(RegisterGlobalHandler::with_nodeinfo::<Update, _>(ctx.address().recipient()));
@@ -66,7 +79,7 @@ impl Supervised for ProcessRegistry {
 
             let bcast = Update(plist).make_broadcast(None);
 
-            let bcast = NodeController::from_registry().do_send(bcast);
+            let bcast = this.node.controller().do_send(bcast);
         });
     }
 }
@@ -86,6 +99,7 @@ impl Handler<FromNode<Update>> for ProcessRegistry {
         for new in msg.inner.newids.array_chunks::<16>() {
             let new = Uuid::from_bytes(*new);
             log::info!("Proc: {} running on {}", new, node);
+            self.unreachable.remove(&new);
             self.nodes.insert(new, node);
         }
     }
@@ -95,9 +109,42 @@ impl Handler<NodeStatus> for ProcessRegistry {
     type Result = ();
 
     fn handle(&mut self, msg: NodeStatus, ctx: &mut Context<Self>) -> Self::Result {
+        if let NodeStatus::Disconnected(id, ref reason) = msg {
+            let lost: Vec<Uuid> = self.nodes.iter()
+                .filter(|(_, node)| **node == id)
+                .map(|(proc, _)| *proc)
+                .collect();
+
+            // Processes of nodes which left the cluster are gone for good
+            let left = matches!(reason, DisconnectReason::Shutdown | DisconnectReason::Restarted);
+            if left {
+                log::info!("Node {} left, forgetting its processes", id);
+            } else {
+                log::info!("Marking processes on node {} as unreachable", id);
+            }
+            for proc in lost {
+                self.nodes.remove(&proc);
+                if !left {
+                    self.unreachable.insert(proc, id);
+                }
+            }
+            if left {
+                self.unreachable.retain(|_, node| *node != id);
+            }
+        }
+
+        if let NodeStatus::ReconnectFailed(id) = msg {
+            // Node is not coming back, at least not under this id
+            log::info!("Giving up on node {}, forgetting its processes", id);
+            self.unreachable.retain(|_, node| *node != id);
+        }
+
         if let NodeStatus::Connected(id) = msg {
+            // The node will announce its current process list, forget the stale one
+            self.unreachable.retain(|_, node| *node != id);
+
             log::info!("Announcing process list to new node: {}", id);
-            let control = NodeController::from_registry();
+            let control = self.node.controller();
             let update = ProcessList {
                 newids: self.local.keys().fold(vec![], fold_uuids),
                 delids: vec![],
@@ -175,7 +222,9 @@ impl Handler<MethodCall> for ProcessRegistry {
                     nodeid: *node,
                     inner: msg,
                 };
-                Response::fut(NodeController::from_registry().send(msg).map(|x| x.unwrap()))
+                Response::fut(self.node.controller().send(msg).map(|x| x.unwrap()))
+            } else if self.unreachable.contains_key(&msg.procid.unwrap()) {
+                Response::reply(Err(DispatchError::NodeNotFound))
             } else {
                 Response::reply(Err(DispatchError::ProcessNotFound))
             }
@@ -197,8 +246,10 @@ impl Handler<Broadcast> for ProcessRegistry {
                     nodeid: *node,
                     inner: msg,
                 };
-                NodeController::from_registry().do_send(msg);
+                self.node.controller().do_send(msg);
                 Response::reply(Ok(()))
+            } else if self.unreachable.contains_key(&msg.procid.unwrap()) {
+                Response::reply(Err(DispatchError::NodeNotFound))
             } else {
                 Response::reply(Err(DispatchError::ProcessNotFound))
             }
@@ -10,6 +10,16 @@ pub struct Net {
     pub request: ::std::option::Option<Request>,
     #[prost(message, optional, tag="5")]
     pub response: ::std::option::Option<Response>,
+    #[prost(message, optional, tag="6")]
+    pub auth: ::std::option::Option<Auth>,
+    #[prost(message, optional, tag="7")]
+    pub chunk: ::std::option::Option<Chunk>,
+    #[prost(message, optional, tag="8")]
+    pub peers: ::std::option::Option<Peers>,
+    #[prost(message, optional, tag="9")]
+    pub goodbye: ::std::option::Option<Goodbye>,
+    #[prost(message, optional, tag="10")]
+    pub metadata: ::std::option::Option<Metadata>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PingPong {
@@ -18,6 +28,96 @@ pub struct PingPong {
 pub struct Meta {
     #[prost(bytes, required, tag="1")]
     pub nodeid: std::vec::Vec<u8>,
+    /// Challenge for cookie authentication, sent only if the node has a cookie configured
+    #[prost(bytes, optional, tag="2")]
+    pub nonce: ::std::option::Option<std::vec::Vec<u8>>,
+    /// Newest version of the wire protocol the node speaks. Linked nodes use the newest version both of them speak
+    #[prost(uint32, optional, tag="3")]
+    pub protocol: ::std::option::Option<u32>,
+    /// Version of quix running on the node
+    #[prost(string, optional, tag="4")]
+    pub version: ::std::option::Option<std::string::String>,
+    /// Bitset of optional features supported by the node
+    #[prost(uint64, optional, tag="5")]
+    pub capabilities: ::std::option::Option<u64>,
+    /// Address the node accepts connections on, announced to the rest of the cluster
+    #[prost(string, optional, tag="6")]
+    pub listen: ::std::option::Option<std::string::String>,
+    /// Hidden nodes are not announced to other nodes, and don't connect to the nodes they learn about
+    #[prost(bool, optional, tag="7")]
+    pub hidden: ::std::option::Option<bool>,
+    /// Sequence number of the link, sent by the dialing node. Decides which link is kept when nodes dial each other
+    #[prost(uint64, optional, tag="8")]
+    pub link: ::std::option::Option<u64>,
+    /// Human readable name of the node, unique in the cluster
+    #[prost(string, optional, tag="9")]
+    pub name: ::std::option::Option<std::string::String>,
+    #[prost(message, optional, tag="10")]
+    pub metadata: ::std::option::Option<Metadata>,
+    /// Oldest version of the wire protocol the node speaks, same as protocol if missing
+    #[prost(uint32, optional, tag="11")]
+    pub min_protocol: ::std::option::Option<u32>,
+}
+/// Information published by a node, sent in Meta and again whenever it changes
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Metadata {
+    /// Roles of the node, like "ingest" or "query"
+    #[prost(string, repeated, tag="1")]
+    pub roles: ::std::vec::Vec<std::string::String>,
+    #[prost(map="string, string", tag="2")]
+    pub values: ::std::collections::HashMap<std::string::String, std::string::String>,
+}
+/// Proof of knowing the cookie, sent after Meta by the dialing node first, and by the accepting node
+/// only once the proof of the dialing node is verified.
+/// HMAC-SHA256(cookie, role || dialer id || acceptor id || len || dialer nonce || len || acceptor nonce),
+/// where role is 1 for the dialing and 2 for the accepting node, and len is the nonce length as big endian u32
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Auth {
+    #[prost(bytes, required, tag="1")]
+    pub digest: std::vec::Vec<u8>,
+}
+/// Part of an encoded Net frame, which was too large to be sent at once.
+/// Chunks of different frames can be interleaved with each other and with regular traffic
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Chunk {
+    /// Identifies the split frame, unique per link and direction
+    #[prost(uint64, required, tag="1")]
+    pub id: u64,
+    #[prost(bytes, required, tag="2")]
+    pub data: std::vec::Vec<u8>,
+    /// Set on the final chunk, after which the frame is reassembled and handled
+    #[prost(bool, optional, tag="3")]
+    pub last: ::std::option::Option<bool>,
+}
+/// Sent right before the sender closes the link, so the receiver knows not to reconnect
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Goodbye {
+    #[prost(enumeration="CloseReason", optional, tag="1")]
+    pub reason: ::std::option::Option<i32>,
+}
+/// Nodes known to the sender, the receiver connects to those it is not linked with yet
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Peers {
+    #[prost(message, repeated, tag="1")]
+    pub peers: ::std::vec::Vec<Peer>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Peer {
+    #[prost(bytes, required, tag="1")]
+    pub nodeid: std::vec::Vec<u8>,
+    #[prost(string, required, tag="2")]
+    pub addr: std::string::String,
+}
+/// Sent over UDP multicast, so nodes on the same network can find each other
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Announce {
+    /// Nodes only connect to announcements of their own cluster
+    #[prost(string, required, tag="1")]
+    pub cluster: std::string::String,
+    #[prost(bytes, required, tag="2")]
+    pub nodeid: std::vec::Vec<u8>,
+    #[prost(string, required, tag="3")]
+    pub addr: std::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Request {
@@ -29,6 +129,9 @@ pub struct Request {
     pub methodid: u32,
     #[prost(bytes, required, tag="4")]
     pub body: std::vec::Vec<u8>,
+    /// Milliseconds the caller waits for the response, work can be dropped afterwards
+    #[prost(uint32, optional, tag="5")]
+    pub timeout: ::std::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Response {
@@ -53,4 +156,18 @@ pub enum InvokeError {
     MessageFormat = 4,
     /// Not sent but used internally, message handling timed out.
     Timeout = 5,
+    /// Not sent but used internally, link to the remote node was lost.
+    ConnectionLost = 6,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CloseReason {
+    /// Other link between the nodes was kept
+    Duplicate = 1,
+    /// Name of the node is used by another node of the cluster
+    NameTaken = 2,
+    /// Node is leaving the cluster. Requests in flight are still answered, but no new ones are accepted
+    Shutdown = 3,
+    /// Dialing node failed cookie authentication, sent by the accepting node instead of its own Auth
+    Unauthorized = 4,
 }
@@ -0,0 +1,429 @@
+//! Deterministic simulation of a cluster, for tests.
+//!
+//! All nodes run in the current actix system and talk over a simulated network, which can
+//! partition, drop, delay and reorder frames between any pair of nodes. Time is virtual,
+//! heartbeats and process table gossip only advance with [Sim::advance].
+//!
+//! Frames are recognized by their length header, so rules only apply to plaintext links
+use crate::import::*;
+
+use std::io;
+use std::sync::Mutex;
+use futures::channel::mpsc;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::Instant;
+
+use crate::node::{
+    Node, NodeConfig, NodeAddr, Connect, ListNodes, Transport, Listener, BoxConnection,
+};
+
+/// Granularity of the virtual clock
+const STEP: Duration = Duration::from_millis(10);
+
+/// Upper bound of the extra delay of reordered frames
+const REORDER_WINDOW: Duration = Duration::from_millis(50);
+
+/// Behaviour of the network in one direction between two nodes
+#[derive(Debug, Clone, Default)]
+pub struct LinkRules {
+    /// All frames are lost, and new connections are refused
+    pub partitioned: bool,
+    /// Probability that a frame is lost
+    pub drop: f64,
+    /// Probability that a frame is delayed, so frames sent after it can overtake it
+    pub reorder: f64,
+    /// Delay of every frame
+    pub latency: Duration,
+}
+
+struct State {
+    rng: StdRng,
+    rules: HashMap<(usize, usize), LinkRules>,
+    listeners: HashMap<String, (usize, mpsc::UnboundedSender<(BoxConnection, NodeAddr)>)>,
+    /// Connections closed by one side during a partition, the other side finds out once it heals
+    severed: Vec<(usize, usize, mpsc::UnboundedSender<(Instant, Bytes)>)>,
+}
+
+/// Simulated network shared by all nodes of a [Sim]
+#[derive(Clone)]
+pub struct SimNet(Arc<Mutex<State>>);
+
+impl SimNet {
+    /// Network with no faults, random decisions are taken from rng seeded by `seed`
+    pub fn new(seed: u64) -> Self {
+        SimNet(Arc::new(Mutex::new(State {
+            rng: StdRng::seed_from_u64(seed),
+            rules: HashMap::new(),
+            listeners: HashMap::new(),
+            severed: vec![],
+        })))
+    }
+
+    /// Change the rules for frames sent from node `from` to node `to`
+    pub fn update(&self, from: usize, to: usize, f: impl FnOnce(&mut LinkRules)) {
+        let mut state = self.0.lock().unwrap();
+        f(state.rules.entry((from, to)).or_default());
+    }
+
+    /// Close connections severed between nodes `a` and `b`
+    fn reconnect(&self, a: usize, b: usize) {
+        let mut state = self.0.lock().unwrap();
+        state.severed.retain(|(from, to, _)| !(*from == a && *to == b || *from == b && *to == a));
+    }
+
+    fn partitioned(&self, a: usize, b: usize) -> bool {
+        let state = self.0.lock().unwrap();
+        let rules = |k| state.rules.get(&k).map_or(false, |r: &LinkRules| r.partitioned);
+        rules((a, b)) || rules((b, a))
+    }
+
+    /// Decide fate of a frame, and schedule its delivery
+    fn route(&self, stream: &SimStream, frame: Bytes) {
+        let mut state = self.0.lock().unwrap();
+        let State { rng, rules, .. } = &mut *state;
+        let rules = rules.get(&(stream.from, stream.to)).cloned().unwrap_or_default();
+
+        if rules.partitioned || rng.gen_bool(rules.drop.max(0.0).min(1.0)) {
+            log::trace!("Dropping frame from node {} to {}", stream.from, stream.to);
+            return;
+        }
+        let at = Instant::now() + rules.latency;
+
+        if rng.gen_bool(rules.reorder.max(0.0).min(1.0)) {
+            // Bypass the ordered queue
+            let extra = Duration::from_millis(rng.gen_range(1, REORDER_WINDOW.as_millis() as u64 + 1));
+            if let Some(peer) = stream.peer.clone() {
+                actix_rt::spawn(async move {
+                    tokio::time::delay_until(at + extra).await;
+                    let _ = peer.unbounded_send(frame);
+                });
+            }
+        } else {
+            let _ = stream.queue.unbounded_send((at, frame));
+        }
+    }
+}
+
+/// Transport of a single simulated node, listening on and dialing `mem://` addresses
+#[derive(Clone)]
+pub struct SimTransport {
+    net: SimNet,
+    node: usize,
+}
+
+impl std::fmt::Debug for SimTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SimTransport({})", self.node)
+    }
+}
+
+impl SimTransport {
+    pub fn new(net: SimNet, node: usize) -> Self {
+        SimTransport { net, node }
+    }
+}
+
+impl Transport for SimTransport {
+    fn listen(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<(Listener, NodeAddr)>> {
+        let name = match addr {
+            NodeAddr::Memory(name) => name.clone(),
+            other => {
+                let err = io::Error::new(io::ErrorKind::InvalidInput, format!("Not a simulated address: {}", other));
+                return Box::pin(futures::future::err(err));
+            }
+        };
+        let (tx, rx) = mpsc::unbounded();
+        self.net.0.lock().unwrap().listeners.insert(name, (self.node, tx));
+        let listener: Listener = Box::pin(rx.map(Ok));
+        Box::pin(futures::future::ok((listener, addr.clone())))
+    }
+
+    fn dial(&self, addr: &NodeAddr) -> BoxFuture<'static, io::Result<BoxConnection>> {
+        let refused = || -> BoxFuture<'static, io::Result<BoxConnection>> {
+            let err = io::Error::new(io::ErrorKind::ConnectionRefused, format!("Node unreachable: {}", addr));
+            Box::pin(futures::future::err(err))
+        };
+        let listener = match addr {
+            NodeAddr::Memory(name) => self.net.0.lock().unwrap().listeners.get(name).cloned(),
+            _ => None,
+        };
+        let (to, listener) = match listener {
+            Some(listener) => listener,
+            None => return refused(),
+        };
+        if self.net.partitioned(self.node, to) {
+            return refused();
+        }
+
+        let (local, remote) = SimStream::pair(self.net.clone(), self.node, to);
+        let from = Sim::addr(self.node);
+        if listener.unbounded_send((Box::new(remote), from)).is_err() {
+            return refused();
+        }
+        Box::pin(futures::future::ok(Box::new(local) as BoxConnection))
+    }
+}
+
+/// One end of a simulated connection
+struct SimStream {
+    net: SimNet,
+    from: usize,
+    to: usize,
+    /// Written bytes, which don't form a complete frame yet
+    partial: BytesMut,
+    /// Frames delivered in order, once their time comes
+    queue: mpsc::UnboundedSender<(Instant, Bytes)>,
+    /// Reordered frames skip the queue, dropped once the stream is shut down
+    peer: Option<mpsc::UnboundedSender<Bytes>>,
+    rx: mpsc::UnboundedReceiver<Bytes>,
+    /// Data received, but not yet read
+    buf: Bytes,
+}
+
+impl SimStream {
+    fn pair(net: SimNet, a: usize, b: usize) -> (SimStream, SimStream) {
+        let (atx, arx) = mpsc::unbounded();
+        let (btx, brx) = mpsc::unbounded();
+        let stream = |from, to, peer: mpsc::UnboundedSender<Bytes>, rx| {
+            let (queue, queued) = mpsc::unbounded();
+            Self::deliver(queued, peer.clone());
+            SimStream {
+                net: net.clone(),
+                from,
+                to,
+                partial: BytesMut::new(),
+                queue,
+                peer: Some(peer),
+                rx,
+                buf: Bytes::new(),
+            }
+        };
+        (stream(a, b, btx, arx), stream(b, a, atx, brx))
+    }
+
+    /// Stop sending, the peer reads end of stream after frames already in flight
+    fn close(&mut self) {
+        if self.peer.take().is_none() {
+            return;
+        }
+        let (closed, _) = mpsc::unbounded();
+        let queue = std::mem::replace(&mut self.queue, closed);
+        if self.net.partitioned(self.from, self.to) {
+            self.net.0.lock().unwrap().severed.push((self.from, self.to, queue));
+        }
+    }
+
+    fn deliver(mut queued: mpsc::UnboundedReceiver<(Instant, Bytes)>, peer: mpsc::UnboundedSender<Bytes>) {
+        actix_rt::spawn(async move {
+            while let Some((at, frame)) = queued.next().await {
+                tokio::time::delay_until(at).await;
+                if peer.unbounded_send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl AsyncRead for SimStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.buf.is_empty() {
+            match futures::ready!(self.rx.poll_next_unpin(cx)) {
+                Some(data) => self.buf = data,
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+        let len = buf.len().min(self.buf.len());
+        buf[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.advance(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for SimStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.queue.is_closed() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let this = self.get_mut();
+        this.partial.extend_from_slice(buf);
+
+        while this.partial.len() >= 4 {
+            let header = u32::from_be_bytes([this.partial[0], this.partial[1], this.partial[2], this.partial[3]]);
+            // Highest bit marks compressed frames
+            let len = (header & !(1 << 31)) as usize;
+            if this.partial.len() < len + 4 {
+                break;
+            }
+            let frame = this.partial.split_to(len + 4).freeze();
+            this.net.clone().route(this, frame);
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for SimStream {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Cluster of nodes running on a simulated network, with virtual time.
+///
+/// Only one simulation can run in an actix system, since it pauses the clock of the runtime
+pub struct Sim {
+    net: SimNet,
+    nodes: Vec<Node>,
+    ids: Vec<Uuid>,
+}
+
+impl Sim {
+    /// Address node `i` listens on
+    pub fn addr(i: usize) -> NodeAddr {
+        NodeAddr::Memory(format!("sim-{}", i))
+    }
+
+    /// Start `n` nodes linked in a full mesh
+    pub async fn start(n: usize, seed: u64) -> Sim {
+        Self::start_with(n, seed, |_, cfg| cfg).await
+    }
+
+    /// Start `n` nodes linked in a full mesh, `f` can adjust configuration of each node.
+    ///
+    /// Nodes must not be hidden, since they would never become part of the mesh
+    pub async fn start_with(n: usize, seed: u64, mut f: impl FnMut(usize, NodeConfig) -> NodeConfig) -> Sim {
+        tokio::time::pause();
+        let net = SimNet::new(seed);
+
+        let mut nodes = Vec::with_capacity(n);
+        let mut ids = Vec::with_capacity(n);
+        for i in 0..n {
+            let config = f(i, NodeConfig {
+                // Stable ids make runs with the same seed reproducible
+                id: Uuid::from_u128(i as u128 + 1),
+                listen: vec![Self::addr(i)],
+                transport: Some(Arc::new(SimTransport::new(net.clone(), i))),
+                ..Default::default()
+            });
+            ids.push(config.id);
+            nodes.push(Node::start(config));
+        }
+
+        // Nodes join through the first one, and find each other through gossip
+        for i in 1..n {
+            nodes[i].controller().send(Connect { addr: Self::addr(0) }).await
+                .unwrap()
+                .unwrap();
+        }
+
+        let sim = Sim { net, nodes, ids };
+        while !sim.is_mesh().await {
+            sim.step().await;
+        }
+        sim
+    }
+
+    /// Whether every node is linked with all the others
+    async fn is_mesh(&self) -> bool {
+        for node in &self.nodes {
+            let linked = node.controller().send(ListNodes::default()).await.unwrap();
+            if linked.len() + 1 < self.nodes.len() {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn node(&self, i: usize) -> &Node {
+        &self.nodes[i]
+    }
+
+    pub fn id(&self, i: usize) -> Uuid {
+        self.ids[i]
+    }
+
+    pub fn net(&self) -> &SimNet {
+        &self.net
+    }
+
+    /// Cut the network between nodes `a` and `b` in both directions
+    pub fn partition(&self, a: usize, b: usize) {
+        self.net.update(a, b, |r| r.partitioned = true);
+        self.net.update(b, a, |r| r.partitioned = true);
+    }
+
+    /// Cut node `a` off from all other nodes
+    pub fn isolate(&self, a: usize) {
+        for b in (0..self.nodes.len()).filter(|b| *b != a) {
+            self.partition(a, b);
+        }
+    }
+
+    /// Remove partition between nodes `a` and `b`
+    pub fn heal(&self, a: usize, b: usize) {
+        self.net.update(a, b, |r| r.partitioned = false);
+        self.net.update(b, a, |r| r.partitioned = false);
+        self.net.reconnect(a, b);
+    }
+
+    /// Remove all faults from the network
+    pub fn heal_all(&self) {
+        let mut state = self.net.0.lock().unwrap();
+        state.rules.clear();
+        state.severed.clear();
+    }
+
+    /// Lose frames sent from `from` to `to` with given probability
+    pub fn set_drop(&self, from: usize, to: usize, probability: f64) {
+        self.net.update(from, to, |r| r.drop = probability);
+    }
+
+    /// Reorder frames sent from `from` to `to` with given probability
+    pub fn set_reorder(&self, from: usize, to: usize, probability: f64) {
+        self.net.update(from, to, |r| r.reorder = probability);
+    }
+
+    /// Delay frames sent from `from` to `to`
+    pub fn set_latency(&self, from: usize, to: usize, latency: Duration) {
+        self.net.update(from, to, |r| r.latency = latency);
+    }
+
+    /// Let the cluster run for `dur` of virtual time
+    pub async fn advance(&self, dur: Duration) {
+        let until = Instant::now() + dur;
+        while Instant::now() < until {
+            self.step().await;
+        }
+    }
+
+    /// Run `fut` on node `i`, advancing virtual time until it completes
+    pub async fn run<F: Future>(&self, i: usize, fut: F) -> F::Output {
+        let fut = self.nodes[i].scope(fut);
+        futures::pin_mut!(fut);
+        loop {
+            if let Poll::Ready(out) = futures::poll!(fut.as_mut()) {
+                return out;
+            }
+            self.step().await;
+        }
+    }
+
+    async fn step(&self) {
+        tokio::time::advance(STEP).await;
+        // Let actors process messages triggered by expired timers
+        for _ in 0..16 {
+            tokio::task::yield_now().await;
+        }
+    }
+}
@@ -10,6 +10,11 @@ impl<M> Message for RegisterRecipient<M> where M: Message + Send,
                                                M::Result: Send
 { type Result = Result<Uuid, std::convert::Infallible>; }
 
+/// Remove recipient registered with [RegisterRecipient], identified by the returned id
+pub struct UnregisterRecipient(pub Uuid);
+
+impl Message for UnregisterRecipient { type Result = (); }
+
 pub trait RpcMethod: Sized + Message {
     const NAME: &'static str;
     // Unique ID of the service method. Should be crc64 of Name
@@ -40,6 +45,7 @@ pub trait RpcMethod: Sized + Message {
             procid: id,
             body: RpcMethod::to_buf(self).unwrap(),
             method: Self::ID,
+            timeout: None,
         }
     }
 }
@@ -0,0 +1,23 @@
+#!/bin/sh
+# Generate certificates used by tests/tls.rs: a CA, and node certificates issued for
+# `<nodeid>.quix`, usable both as TLS server and client, like nodes of a real cluster need.
+set -e
+cd "$(dirname "$0")"
+
+openssl req -x509 -new -nodes -days 36500 -subj "/CN=quix test CA" \
+    -newkey ec -pkeyopt ec_paramgen_curve:P-256 -keyout ca.key -out ca.pem \
+    -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign"
+
+node() {
+    openssl req -new -nodes -subj "/CN=$1" \
+        -newkey ec -pkeyopt ec_paramgen_curve:P-256 -keyout "$1.key" -out "$1.csr"
+    printf "basicConstraints=CA:FALSE\nkeyUsage=critical,digitalSignature\nextendedKeyUsage=serverAuth,clientAuth\nsubjectAltName=DNS:%s.quix\n" "$2" > "$1.ext"
+    openssl x509 -req -in "$1.csr" -CA ca.pem -CAkey ca.key -CAcreateserial -days 36500 -sha256 \
+        -extfile "$1.ext" -out "$1.pem"
+    rm "$1.csr" "$1.ext"
+}
+
+node node-a 00000000-0000-0000-0000-00000000000a
+node node-b 00000000-0000-0000-0000-00000000000b
+
+rm -f ca.key ca.srl
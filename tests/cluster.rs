@@ -0,0 +1,382 @@
+mod common;
+
+use actix::Actor;
+use quix::node::{
+    Node, NodeConfig, NodeAddr, NodeId, Connect, ListNodes, ListenAddrs, LinkError, UpdateMetadata, parse_seeds,
+    NodeStatus, MonitorNode, DisconnectReason, ReconnectConfig, Shutdown,
+};
+use quix::util::RegisterRecipient;
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+use common::{EventLog, wait_for_links};
+
+fn mem(name: &str, i: usize) -> NodeAddr {
+    NodeAddr::Memory(format!("{}-{}", name, i))
+}
+
+#[test]
+fn test_nodes_in_one_system() {
+    actix::run(async move {
+        let nodes: Vec<Node> = (0..5).map(|i| Node::start(NodeConfig {
+            listen: vec![mem("chain", i)],
+            ..Default::default()
+        })).collect();
+
+        for i in 1..nodes.len() {
+            nodes[i].controller().send(Connect { addr: mem("chain", i - 1) }).await.unwrap().unwrap();
+        }
+
+        // Nodes learn about the rest of the chain from their neighbours
+        for node in &nodes {
+            assert_eq!(wait_for_links(node, 4).await, 4);
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_hidden_node() {
+    actix::run(async move {
+        let nodes: Vec<Node> = (0..3).map(|i| Node::start(NodeConfig {
+            listen: vec![mem("hidden", i)],
+            hidden: i == 2,
+            ..Default::default()
+        })).collect();
+
+        nodes[1].controller().send(Connect { addr: mem("hidden", 0) }).await.unwrap().unwrap();
+        nodes[2].controller().send(Connect { addr: mem("hidden", 1) }).await.unwrap().unwrap();
+
+        assert_eq!(wait_for_links(&nodes[1], 2).await, 2);
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        // Hidden node is not announced, and does not connect to nodes it learns about
+        assert_eq!(nodes[0].controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        assert_eq!(nodes[2].controller().send(ListNodes::default()).await.unwrap().len(), 1);
+    }).unwrap();
+}
+
+#[test]
+fn test_simultaneous_connect() {
+    actix::run(async move {
+        let nodes: Vec<Node> = (0..2).map(|i| Node::start(NodeConfig {
+            id: Uuid::from_u128(i as u128 + 1),
+            listen: vec![mem("dup", i)],
+            ..Default::default()
+        })).collect();
+        let logs: Vec<EventLog> = (0..2).map(|_| EventLog::default()).collect();
+        for (node, log) in nodes.iter().zip(&logs) {
+            let log = log.clone().start().recipient();
+            node.controller().send(RegisterRecipient(log)).await.unwrap().unwrap();
+        }
+
+        let (a, b) = futures::join!(
+            nodes[0].controller().send(Connect { addr: mem("dup", 1) }),
+            nodes[1].controller().send(Connect { addr: mem("dup", 0) }),
+        );
+        let (a, _) = (a.unwrap().unwrap(), b.unwrap().unwrap());
+        for (i, (node, log)) in nodes.iter().zip(&logs).enumerate() {
+            let other = Uuid::from_u128(2 - i as u128);
+            let recipient = log.clone().start().recipient();
+            node.controller().send(MonitorNode { node: other, recipient }).await.unwrap();
+        }
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+
+        // Both nodes keep the link dialed by the node with lower id, the other one is closed
+        assert!(a.connected());
+        for node in &nodes {
+            assert_eq!(node.controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        }
+        // without either node seeing the other one leave
+        for log in &logs {
+            assert!(log.status.lock().unwrap().iter().all(|s| matches!(s, NodeStatus::Connected(_))));
+            assert!(log.down.lock().unwrap().is_empty());
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_join_through_seeds() {
+    actix::run(async move {
+        let seeds = vec![mem("seed", 0), mem("seed", 1)];
+        // Second seed is not up yet when the others start, and is retried
+        let nodes: Vec<Node> = [0, 2, 1].iter().map(|i| Node::start(NodeConfig {
+            listen: vec![mem("seed", *i)],
+            seeds: seeds.clone(),
+            ..Default::default()
+        })).collect();
+
+        for node in &nodes {
+            assert_eq!(wait_for_links(node, 2).await, 2);
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_parse_seeds() {
+    let seeds = parse_seeds("tcp://127.0.0.1:9000, 127.0.0.1:9001\n# comment\n\nmem://a # trailing").unwrap();
+    assert_eq!(seeds, vec![
+        "127.0.0.1:9000".parse().unwrap(),
+        "127.0.0.1:9001".parse().unwrap(),
+        NodeAddr::Memory("a".to_string()),
+    ]);
+    assert!(parse_seeds("127.0.0.1").is_err());
+}
+
+#[test]
+fn test_node_names() {
+    actix::run(async move {
+        let names = ["worker-0", "worker-1", "worker-0"];
+        let nodes: Vec<Node> = (0..3).map(|i| Node::start(NodeConfig {
+            name: Some(names[i].to_string()),
+            listen: vec![mem("names", i)],
+            // Don't wait for the name to be released
+            reconnect: ReconnectConfig { max_attempts: Some(1), ..Default::default() },
+            ..Default::default()
+        })).collect();
+
+        nodes[1].controller().send(Connect { addr: mem("names", 0) }).await.unwrap().unwrap();
+
+        let id = nodes[1].scope(NodeId::by_name("worker-0")).await.unwrap();
+        let expected = nodes[0].scope(NodeId::by_name("worker-0")).await.unwrap();
+        assert_eq!(id.0, expected.0);
+        assert!(nodes[1].scope(NodeId::by_name("worker-2")).await.is_err());
+
+        // Name of the first node is taken
+        let res = nodes[2].controller().send(Connect { addr: mem("names", 0) }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::NameTaken(_))));
+
+        // Only the second node knows about the conflict, and closes the link
+        nodes[2].controller().send(Connect { addr: mem("names", 1) }).await.unwrap().unwrap();
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        assert_eq!(nodes[1].controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        assert!(nodes[2].controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+#[test]
+fn test_connect_failure() {
+    actix::run(async move {
+        // Reconnects are retried forever by default, explicit connects give up eventually
+        let node = Node::start(NodeConfig {
+            listen: vec![mem("connect-failure", 0)],
+            reconnect: ReconnectConfig { initial: Duration::from_millis(1), connect_attempts: 3, ..Default::default() },
+            ..Default::default()
+        });
+        let log = EventLog::default();
+        node.controller().send(RegisterRecipient(log.clone().start().recipient())).await.unwrap().unwrap();
+
+        let addr = mem("connect-failure", 1);
+        let res = node.controller().send(Connect { addr: addr.clone() }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Io(_))));
+
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        let status = log.status.lock().unwrap();
+        assert_eq!(status.len(), 3);
+        assert!(matches!(status[0], NodeStatus::Connecting(ref a, 1) if *a == addr));
+        assert!(matches!(status[1], NodeStatus::Connecting(ref a, 2) if *a == addr));
+        assert!(matches!(status[2], NodeStatus::ConnectFailed(ref a) if *a == addr));
+    }).unwrap();
+}
+
+#[test]
+fn test_restarted_node() {
+    actix::run(async move {
+        let named = |id: u128, listen: usize| NodeConfig {
+            id: Uuid::from_u128(id),
+            name: Some("restarted".to_string()),
+            listen: vec![mem("restart", listen)],
+            advertise: Some(mem("restart", 0)),
+            ..Default::default()
+        };
+        let old = Node::start(named(1, 0));
+        let seed = Node::start(NodeConfig {
+            listen: vec![mem("restart", 1)],
+            ..Default::default()
+        });
+        let log = EventLog::default();
+        seed.controller().send(RegisterRecipient(log.clone().start().recipient())).await.unwrap().unwrap();
+        seed.controller().send(Connect { addr: mem("restart", 0) }).await.unwrap().unwrap();
+
+        // New instance advertises the same address, and replaces the old one which did not leave
+        let new = Node::start(named(2, 2));
+        new.controller().send(Connect { addr: mem("restart", 1) }).await.unwrap().unwrap();
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+
+        let nodes = seed.controller().send(ListNodes::default()).await.unwrap();
+        assert_eq!(nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![Uuid::from_u128(2)]);
+        assert!(log.status.lock().unwrap().iter().any(|s| {
+            matches!(s, NodeStatus::Disconnected(id, DisconnectReason::Restarted) if *id == Uuid::from_u128(1))
+        }));
+        assert!(old.controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+#[test]
+fn test_name_released() {
+    actix::run(async move {
+        let named = |id: u128, listen: usize| NodeConfig {
+            id: Uuid::from_u128(id),
+            name: Some("released".to_string()),
+            listen: vec![mem("release", listen)],
+            reconnect: ReconnectConfig { initial: Duration::from_millis(50), ..Default::default() },
+            ..Default::default()
+        };
+        let old = Node::start(named(1, 0));
+        let seed = Node::start(NodeConfig {
+            listen: vec![mem("release", 1)],
+            ..Default::default()
+        });
+        seed.controller().send(Connect { addr: mem("release", 0) }).await.unwrap().unwrap();
+
+        // Node at a different address can't use the name
+        let new = Node::start(named(2, 2));
+        new.controller().send(Connect { addr: mem("release", 1) }).await.unwrap().unwrap();
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        let ids = || async {
+            seed.controller().send(ListNodes::default()).await.unwrap().iter().map(|n| n.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids().await, vec![Uuid::from_u128(1)]);
+
+        // until the old node leaves
+        old.controller().send(Shutdown { drain: Duration::from_millis(100) }).await.unwrap();
+        for _ in 0..100 {
+            if ids().await == vec![Uuid::from_u128(2)] {
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("New node did not take over the name");
+    }).unwrap();
+}
+
+#[test]
+fn test_roles_and_metadata() {
+    actix::run(async move {
+        let roles = [vec!["ingest"], vec!["query"], vec!["ingest", "query"]];
+        let nodes: Vec<Node> = (0..3).map(|i| Node::start(NodeConfig {
+            listen: vec![mem("roles", i)],
+            roles: roles[i].iter().map(|r| r.to_string()).collect(),
+            ..Default::default()
+        })).collect();
+        for i in 1..3 {
+            nodes[i].controller().send(Connect { addr: mem("roles", 0) }).await.unwrap().unwrap();
+        }
+        wait_for_links(&nodes[0], 2).await;
+
+        let query = nodes[0].controller().send(ListNodes { role: Some("query".to_string()) }).await.unwrap();
+        assert_eq!(query.len(), 2);
+        let ingest = nodes[0].controller().send(ListNodes { role: Some("ingest".to_string()) }).await.unwrap();
+        assert_eq!(ingest.len(), 1);
+        assert_eq!(ingest[0].addr, Some(mem("roles", 2)));
+
+        let mut metadata = HashMap::new();
+        metadata.insert("dc".to_string(), "eu-1".to_string());
+        nodes[1].controller().send(UpdateMetadata { roles: vec![], metadata }).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+
+        let query = nodes[0].controller().send(ListNodes { role: Some("query".to_string()) }).await.unwrap();
+        assert_eq!(query.len(), 1);
+        let all = nodes[0].controller().send(ListNodes::default()).await.unwrap();
+        let updated = all.iter().find(|n| n.metadata.get("dc").map(String::as_str) == Some("eu-1"));
+        assert!(updated.is_some());
+    }).unwrap();
+}
+
+#[test]
+fn test_current_node() {
+    actix::run(async move {
+        let node = Node::start(NodeConfig {
+            listen: vec![mem("current", 0)],
+            ..Default::default()
+        });
+
+        let current = node.scope(async { Node::current() }).await;
+        assert!(current.controller() == node.controller());
+
+        // Tasks outside of a scope can tell they don't run on a particular node
+        assert!(Node::try_current().is_none());
+        let current = node.scope(async { Node::try_current() }).await.unwrap();
+        assert!(current.controller() == node.controller());
+    }).unwrap();
+}
+
+#[test]
+fn test_listen_on_ipv4_and_ipv6() {
+    actix::run(async move {
+        let node = Node::start(NodeConfig {
+            listen: vec!["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()],
+            ..Default::default()
+        });
+        let addrs = node.controller().send(ListenAddrs).await.unwrap().unwrap();
+        assert_eq!(addrs.len(), 2);
+
+        for addr in &addrs {
+            match addr {
+                NodeAddr::Tcp(bound) => assert_ne!(bound.port(), 0),
+                other => panic!("Unexpected address: {}", other),
+            }
+            let client = Node::start(NodeConfig { listen: vec![], ..Default::default() });
+            client.controller().send(Connect { addr: addr.clone() }).await.unwrap().unwrap();
+
+            // Peers learn the bound port of the first address, not port 0
+            let nodes = client.controller().send(ListNodes::default()).await.unwrap();
+            assert_eq!(nodes[0].addr, Some(addrs[0].clone()));
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_listen_on_all_interfaces() {
+    actix::run(async move {
+        let node = Node::start(NodeConfig {
+            listen: vec!["0.0.0.0:0".parse().unwrap()],
+            ..Default::default()
+        });
+        let port = match node.controller().send(ListenAddrs).await.unwrap().unwrap()[0] {
+            NodeAddr::Tcp(bound) => bound.port(),
+            ref other => panic!("Unexpected address: {}", other),
+        };
+        let local: NodeAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+
+        // Peers learn the address they reached the node at, instead of the unspecified one
+        let client = Node::start(NodeConfig { listen: vec![], ..Default::default() });
+        client.controller().send(Connect { addr: local.clone() }).await.unwrap().unwrap();
+        let nodes = client.controller().send(ListNodes::default()).await.unwrap();
+        assert_eq!(nodes[0].addr, Some(local));
+    }).unwrap();
+}
+
+#[test]
+fn test_advertised_address() {
+    actix::run(async move {
+        let public = mem("public", 0);
+        let node = Node::start(NodeConfig {
+            listen: vec![mem("advertise", 0)],
+            advertise: Some(public.clone()),
+            ..Default::default()
+        });
+        let client = Node::start(NodeConfig { listen: vec![], ..Default::default() });
+        client.controller().send(Connect { addr: mem("advertise", 0) }).await.unwrap().unwrap();
+
+        let nodes = client.controller().send(ListNodes::default()).await.unwrap();
+        assert_eq!(nodes[0].addr, Some(public));
+        assert_eq!(node.controller().send(ListenAddrs).await.unwrap(), Ok(vec![mem("advertise", 0)]));
+    }).unwrap();
+}
+
+#[test]
+fn test_listen_failure() {
+    actix::run(async move {
+        let _first = Node::start(NodeConfig {
+            listen: vec![mem("taken", 0)],
+            ..Default::default()
+        });
+        let second = Node::start(NodeConfig {
+            listen: vec![mem("taken", 0)],
+            ..Default::default()
+        });
+
+        let err = second.controller().send(ListenAddrs).await.unwrap().unwrap_err();
+        assert_eq!(err.addr, Some(mem("taken", 0)));
+        assert_eq!(err.kind, std::io::ErrorKind::AddrInUse);
+    }).unwrap();
+}
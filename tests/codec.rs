@@ -0,0 +1,84 @@
+use quix::node::{NetCodec, CompressionConfig};
+use quix::proto::{Net, PingPong, Request};
+use tokio_util::codec::{Encoder, Decoder};
+use bytes::BytesMut;
+
+fn request(body: Vec<u8>) -> Net {
+    Net {
+        request: Some(Request {
+            procid: None,
+            correlation: Some(1),
+            methodid: 42,
+            body,
+            timeout: None,
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_small_frames_uncompressed() {
+    let mut codec = NetCodec::compressed(CompressionConfig::default());
+    let mut buf = BytesMut::new();
+
+    let ping = Net { ping: Some(PingPong {}), ..Default::default() };
+    codec.encode(ping.clone(), &mut buf).unwrap();
+    assert_eq!(buf[0] & 0x80, 0);
+
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(ping));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_large_frames_compressed() {
+    let mut codec = NetCodec::compressed(CompressionConfig::default());
+    let mut buf = BytesMut::new();
+
+    let req = request(vec![7; 1024 * 1024]);
+    codec.encode(req.clone(), &mut buf).unwrap();
+    assert_ne!(buf[0] & 0x80, 0);
+    assert!(buf.len() < 1024 * 1024);
+
+    // Receiving side decodes compressed frames regardless of its own settings
+    let mut plain = NetCodec::default();
+    assert_eq!(plain.decode(&mut buf).unwrap(), Some(req));
+}
+
+#[test]
+fn test_partial_frame() {
+    let mut codec = NetCodec::default();
+    let mut buf = BytesMut::new();
+
+    let req = request(vec![1, 2, 3]);
+    codec.encode(req.clone(), &mut buf).unwrap();
+    let rest = buf.split_off(5);
+
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.unsplit(rest);
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(req));
+}
+
+#[test]
+fn test_oversized_frame_rejected() {
+    let mut codec = NetCodec::default();
+    let mut buf = BytesMut::new();
+    codec.encode(request(vec![0; 2048]), &mut buf).unwrap();
+
+    // Rejected based on the header alone, without waiting for the rest of the frame
+    buf.truncate(8);
+    let mut limited = NetCodec::default().max_frame_size(1024);
+    let err = limited.decode(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_decompressed_size_limited() {
+    let mut codec = NetCodec::compressed(CompressionConfig::default());
+    let mut buf = BytesMut::new();
+    codec.encode(request(vec![0; 1024 * 1024]), &mut buf).unwrap();
+    assert!(buf.len() < 64 * 1024);
+
+    let mut limited = NetCodec::default().max_frame_size(64 * 1024);
+    let err = limited.decode(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
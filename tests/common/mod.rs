@@ -0,0 +1,46 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+use actix::{Actor, Handler};
+use quix::node::{Node, ListNodes, NodeStatus, NodeDown};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Wait until `node` is linked with `count` other nodes
+pub async fn wait_for_links(node: &Node, count: usize) -> usize {
+    for _ in 0..100 {
+        let linked = node.controller().send(ListNodes::default()).await.unwrap().len();
+        if linked >= count {
+            return linked;
+        }
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+    }
+    node.controller().send(ListNodes::default()).await.unwrap().len()
+}
+
+/// Cluster changes and failures of watched nodes, seen by a node
+#[derive(Default, Clone)]
+pub struct EventLog {
+    pub status: Arc<Mutex<Vec<NodeStatus>>>,
+    pub down: Arc<Mutex<Vec<NodeDown>>>,
+}
+
+impl Actor for EventLog {
+    type Context = actix::Context<Self>;
+}
+
+impl Handler<NodeStatus> for EventLog {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeStatus, _: &mut Self::Context) {
+        self.status.lock().unwrap().push(msg);
+    }
+}
+
+impl Handler<NodeDown> for EventLog {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeDown, _: &mut Self::Context) {
+        self.down.lock().unwrap().push(msg);
+    }
+}
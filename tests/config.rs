@@ -0,0 +1,126 @@
+use quix::node::{NodeConfig, NodeAddr, ListenAddrs, ConfigError};
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Write `content` to a temporary config file named `name`
+fn config_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_toml_config() {
+    let path = config_file("quix-test.toml", r#"
+        id = "6f8c3c1e-5b8a-4a43-9a1c-0d5c2a3e4b11"
+        name = "worker-1"
+        listen = ["127.0.0.1:9400", "[::1]:9400"]
+        advertise = "10.0.0.1:9400"
+        seeds = ["10.0.0.2:9400", "mem://seed"]
+        cookie = "secret"
+        request_timeout_ms = 5000
+        handshake_timeout_ms = 3000
+        roles = ["ingest"]
+
+        [metadata]
+        dc = "eu-1"
+
+        [tls]
+        cert = "/etc/quix/node.pem"
+        key = "/etc/quix/node.key"
+        ca = "/etc/quix/ca.pem"
+    "#);
+
+    let config = NodeConfig::from_file(&path).unwrap();
+    assert_eq!(config.id, Uuid::parse_str("6f8c3c1e-5b8a-4a43-9a1c-0d5c2a3e4b11").unwrap());
+    assert_eq!(config.name.as_deref(), Some("worker-1"));
+    assert_eq!(config.listen, vec!["127.0.0.1:9400".parse().unwrap(), "[::1]:9400".parse().unwrap()]);
+    assert_eq!(config.advertise, Some("10.0.0.1:9400".parse().unwrap()));
+    assert_eq!(config.seeds, vec!["10.0.0.2:9400".parse().unwrap(), NodeAddr::Memory("seed".to_string())]);
+    assert_eq!(config.cookie.as_deref(), Some("secret"));
+    assert_eq!(config.request_timeout, Duration::from_secs(5));
+    assert_eq!(config.handshake_timeout, Duration::from_secs(3));
+    assert_eq!(config.roles, vec!["ingest".to_string()]);
+    assert_eq!(config.metadata.get("dc").map(String::as_str), Some("eu-1"));
+
+    let tls = config.tls.unwrap();
+    assert_eq!(tls.ca, PathBuf::from("/etc/quix/ca.pem"));
+    assert!(!tls.verify_node_id);
+
+    // Missing settings keep their defaults
+    assert_eq!(config.heartbeat_timeout, NodeConfig::default().heartbeat_timeout);
+}
+
+#[test]
+fn test_yaml_config() {
+    let path = config_file("quix-test.yaml", "
+name: worker-2
+listen:
+  - mem://yaml
+heartbeat_interval_ms: 250
+hidden: true
+");
+
+    let config = NodeConfig::from_file(&path).unwrap();
+    assert_eq!(config.name.as_deref(), Some("worker-2"));
+    assert_eq!(config.listen, vec![NodeAddr::Memory("yaml".to_string())]);
+    assert_eq!(config.heartbeat_interval, Duration::from_millis(250));
+    assert!(config.hidden);
+}
+
+#[test]
+fn test_invalid_config() {
+    let path = config_file("quix-test-unknown.toml", "lisen = [\"127.0.0.1:9400\"]");
+    assert!(matches!(NodeConfig::from_file(&path), Err(ConfigError::Parse(..))));
+
+    let path = config_file("quix-test-addr.toml", "listen = [\"localhost\"]");
+    assert!(matches!(NodeConfig::from_file(&path), Err(ConfigError::Invalid { ref key, .. }) if key == "listen"));
+
+    let path = config_file("quix-test.json", "{}");
+    assert!(matches!(NodeConfig::from_file(&path), Err(ConfigError::Parse(..))));
+
+    assert!(matches!(NodeConfig::from_file("/nonexistent/quix.toml"), Err(ConfigError::Io(..))));
+}
+
+#[test]
+fn test_env_config() {
+    let path = config_file("quix-test-env.toml", "name = \"from-file\"\nlisten = [\"mem://file\"]");
+    std::env::set_var("QUIX_CONFIG", &path);
+    std::env::set_var("QUIX_NAME", "from-env");
+    std::env::set_var("QUIX_LISTEN", "127.0.0.1:9401, mem://env");
+    std::env::set_var("QUIX_HEARTBEAT_TIMEOUT_MS", "2000");
+
+    let config = NodeConfig::load().unwrap();
+    assert_eq!(config.name.as_deref(), Some("from-env"));
+    assert_eq!(config.listen, vec!["127.0.0.1:9401".parse().unwrap(), NodeAddr::Memory("env".to_string())]);
+    assert_eq!(config.heartbeat_timeout, Duration::from_secs(2));
+    // Seeds are only read from the environment when they are there
+    assert!(!config.seeds_from_env);
+    std::env::set_var("QUIX_SEEDS", "mem://seed");
+    assert!(NodeConfig::load().unwrap().seeds_from_env);
+    std::env::set_var("QUIX_SEEDS_FROM_ENV", "false");
+    assert!(!NodeConfig::load().unwrap().seeds_from_env);
+    std::env::remove_var("QUIX_SEEDS");
+    std::env::remove_var("QUIX_SEEDS_FROM_ENV");
+
+    std::env::set_var("QUIX_HEARTBEAT_TIMEOUT_MS", "soon");
+    assert!(matches!(NodeConfig::from_env(), Err(ConfigError::Invalid { .. })));
+    std::env::set_var("QUIX_HEARTBEAT_TIMEOUT_MS", "2000");
+
+    std::env::set_var("QUIX_TLS_CERT", "/etc/quix/node.pem");
+    assert!(matches!(NodeConfig::from_env(), Err(ConfigError::Invalid { .. })));
+}
+
+#[test]
+fn test_start() {
+    actix::run(async move {
+        let node = quix::start(NodeConfig {
+            listen: vec![NodeAddr::Memory("start".to_string())],
+            ..Default::default()
+        });
+        // Default node picks up the configuration, instead of listening on the default address
+        let addrs = node.controller().send(ListenAddrs).await.unwrap();
+        assert_eq!(addrs, Ok(vec![NodeAddr::Memory("start".to_string())]));
+    }).unwrap();
+}
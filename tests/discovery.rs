@@ -0,0 +1,37 @@
+use quix::node::{Node, NodeConfig, DiscoveryConfig, ListNodes};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+fn node(port: u16, cluster: &str) -> Node {
+    Node::start(NodeConfig {
+        listen: vec![format!("127.0.0.1:{}", port).parse().unwrap()],
+        discovery: Some(DiscoveryConfig {
+            cluster: cluster.to_string(),
+            group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 98), 9389),
+            interface: Ipv4Addr::LOCALHOST,
+            interval: Duration::from_millis(100),
+        }),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_multicast_discovery() {
+    actix::run(async move {
+        let a = node(9301, "discovery");
+        let b = node(9302, "discovery");
+        let other = node(9303, "other");
+
+        for _ in 0..50 {
+            if a.controller().send(ListNodes::default()).await.unwrap().len() == 1 {
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(a.controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        assert_eq!(b.controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        // Nodes of other clusters are ignored
+        assert!(other.controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
@@ -3,7 +3,6 @@ use actix::{SystemService, Message, Actor, Handler, Running};
 use std::time::Duration;
 use quix::{Process};
 use std::thread::JoinHandle;
-use quix::global::{Global, Set};
 use quix::util::RpcMethod;
 use bytes::{Buf, BufMut};
 use quix::process::DispatchError;
@@ -71,11 +70,11 @@ fn make_node(i: i32) -> JoinHandle<()> {
             tokio::time::delay_for(Duration::from_millis((i * 100) as u64)).await;
 
             let config = NodeConfig {
-                listen: format!("127.0.0.1:900{}", i).parse().unwrap(),
+                listen: vec![format!("127.0.0.1:900{}", i).parse().unwrap()],
                 ..Default::default()
             };
 
-            Global::<NodeConfig>::from_registry().send(Set(config)).await.unwrap();
+            quix::start(config);
 
             if i > 0 {
                 let _ = NodeController::from_registry().send(Connect {
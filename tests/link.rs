@@ -0,0 +1,477 @@
+mod common;
+
+use quix::node::{
+    Node, NodeConfig, NodeAddr, Connect, ListNodes, ListenAddrs, LinkError, NetCodec, Capabilities,
+    Transport, MemoryTransport, BoxConnection, Listener, UpdateMetadata, Shutdown, PROTOCOL_VERSION, MAX_PARTIAL_FRAMES,
+    NodeStatus, MonitorNode, DisconnectReason, ReconnectConfig, NodeId,
+};
+use quix::process::DispatchError;
+use quix::proto::{Net, Meta, Auth, Chunk, Goodbye, CloseReason, Get, Key};
+use quix::util::RegisterRecipient;
+use actix::Actor;
+use futures::{SinkExt, StreamExt};
+use tokio_util::codec::Framed;
+use std::time::Duration;
+use uuid::Uuid;
+use common::{EventLog, wait_for_links};
+
+fn mem(name: &str) -> NodeAddr {
+    NodeAddr::Memory(name.to_string())
+}
+
+fn node(addr: &str, cookie: Option<&str>) -> Node {
+    Node::start(NodeConfig {
+        listen: vec![mem(addr)],
+        cookie: cookie.map(str::to_string),
+        ..Default::default()
+    })
+}
+
+/// Peer speaking the wire protocol directly, to send what a regular node would not
+struct RawPeer {
+    id: Uuid,
+    framed: Framed<BoxConnection, NetCodec>,
+}
+
+impl RawPeer {
+    fn new(conn: BoxConnection) -> RawPeer {
+        RawPeer {
+            id: Uuid::new_v4(),
+            framed: Framed::new(conn, NetCodec::default()),
+        }
+    }
+
+    async fn dial(addr: &str) -> RawPeer {
+        RawPeer::new(MemoryTransport.dial(&mem(addr)).await.unwrap())
+    }
+
+    /// Wait for a node to dial the `listener`
+    async fn accept(listener: &mut Listener) -> RawPeer {
+        RawPeer::new(listener.next().await.unwrap().unwrap().0)
+    }
+
+    /// Metadata a regular node would send, with given capabilities
+    fn meta(&self, caps: Capabilities) -> Meta {
+        Meta {
+            nodeid: self.id.as_bytes().to_vec(),
+            protocol: Some(PROTOCOL_VERSION),
+            capabilities: Some(caps.0),
+            ..Default::default()
+        }
+    }
+
+    async fn send(&mut self, net: Net) {
+        self.framed.send(net).await.unwrap();
+    }
+
+    /// Next frame, `None` once the connection is closed
+    async fn recv(&mut self) -> Option<Net> {
+        self.framed.next().await.and_then(Result::ok)
+    }
+
+    /// Next frame received within `timeout`
+    async fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Net>, tokio::time::Elapsed> {
+        tokio::time::timeout(timeout, self.recv()).await
+    }
+}
+
+#[test]
+fn test_cookie_accepted() {
+    actix::run(async move {
+        let a = node("auth-ok-a", Some("secret"));
+        let b = node("auth-ok-b", Some("secret"));
+
+        b.controller().send(Connect { addr: mem("auth-ok-a") }).await.unwrap().unwrap();
+        assert_eq!(wait_for_links(&a, 1).await, 1);
+    }).unwrap();
+}
+
+#[test]
+fn test_cookie_rejected() {
+    actix::run(async move {
+        let a = node("auth-wrong-a", Some("secret"));
+        let b = node("auth-wrong-b", Some("guess"));
+
+        let res = b.controller().send(Connect { addr: mem("auth-wrong-a") }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Auth)));
+        assert!(a.controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+#[test]
+fn test_missing_cookie() {
+    actix::run(async move {
+        let a = node("auth-missing-a", Some("secret"));
+        let b = node("auth-missing-b", None);
+
+        // Neither the node without a cookie, nor the one requiring it link with the other
+        let res = b.controller().send(Connect { addr: mem("auth-missing-a") }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Auth)));
+        let res = a.controller().send(Connect { addr: mem("auth-missing-b") }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Auth)));
+
+        assert!(a.controller().send(ListNodes::default()).await.unwrap().is_empty());
+        assert!(b.controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+#[test]
+fn test_relay_attack() {
+    actix::run(async move {
+        let a = node("auth-relay-a", Some("secret"));
+        let b = node("auth-relay-b", Some("secret"));
+        for node in &[&a, &b] {
+            node.controller().send(ListenAddrs).await.unwrap().unwrap();
+        }
+
+        // Attacker without the cookie learns the challenge of A
+        let mut to_a = RawPeer::dial("auth-relay-a").await;
+        let mut meta = to_a.meta(Capabilities::AUTH);
+        meta.nonce = Some(vec![1; 32]);
+        to_a.send(Net { meta: Some(meta), ..Default::default() }).await;
+        let challenge = to_a.recv().await.unwrap().meta.unwrap().nonce.unwrap();
+
+        // and asks B to answer it. B waits for the attacker to prove itself first
+        let mut to_b = RawPeer::dial("auth-relay-b").await;
+        let mut meta = to_b.meta(Capabilities::AUTH);
+        meta.nonce = Some(challenge);
+        to_b.send(Net { meta: Some(meta), ..Default::default() }).await;
+        assert!(to_b.recv().await.unwrap().meta.is_some());
+        assert!(to_b.recv_timeout(Duration::from_millis(200)).await.is_err());
+
+        // Invalid proof is rejected without B revealing its digest
+        to_b.send(Net { auth: Some(Auth { digest: vec![0; 32] }), ..Default::default() }).await;
+        while let Some(frame) = to_b.recv().await {
+            assert!(frame.auth.is_none());
+        }
+
+        assert!(a.controller().send(ListNodes::default()).await.unwrap().is_empty());
+        assert!(b.controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+/// Metadata of a node speaking protocol versions `min..=max`
+fn versioned(peer: &RawPeer, min: u32, max: u32) -> Net {
+    let mut meta = peer.meta(Capabilities::CHUNKING);
+    meta.protocol = Some(max);
+    meta.min_protocol = Some(min);
+    Net { meta: Some(meta), ..Default::default() }
+}
+
+#[test]
+fn test_incompatible_protocol() {
+    actix::run(async move {
+        let a = node("proto-incompatible-a", None);
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+
+        // Node speaking only newer versions can't link
+        let mut peer = RawPeer::dial("proto-incompatible-a").await;
+        peer.send(versioned(&peer, PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2)).await;
+        while let Some(frame) = peer.recv().await {
+            assert!(frame.ping.is_none() && frame.peers.is_none());
+        }
+        assert!(a.controller().send(ListNodes::default()).await.unwrap().is_empty());
+
+        // Neither when dialed
+        let (mut listener, _) = MemoryTransport.listen(&mem("proto-incompatible-peer")).await.unwrap();
+        actix::spawn(async move {
+            let mut peer = RawPeer::accept(&mut listener).await;
+            peer.send(versioned(&peer, PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2)).await;
+            while peer.recv().await.is_some() {}
+        });
+        let res = a.controller().send(Connect { addr: mem("proto-incompatible-peer") }).await.unwrap();
+        match res {
+            Err(LinkError::Incompatible { protocol, min_protocol, .. }) => {
+                assert_eq!(protocol, PROTOCOL_VERSION + 2);
+                assert_eq!(min_protocol, PROTOCOL_VERSION + 1);
+            }
+            other => panic!("Expected incompatible protocol, got {:?}", other.map(|_| ())),
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_protocol_range() {
+    actix::run(async move {
+        let a = node("proto-range-a", None);
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+
+        // Newer node which still speaks our version is linked
+        let mut peer = RawPeer::dial("proto-range-a").await;
+        peer.send(versioned(&peer, 1, PROTOCOL_VERSION + 2)).await;
+        let meta = peer.recv().await.unwrap().meta.unwrap();
+        assert_eq!(meta.protocol, Some(PROTOCOL_VERSION));
+        assert_eq!(meta.min_protocol, Some(quix::node::MIN_PROTOCOL_VERSION));
+        assert_eq!(wait_for_links(&a, 1).await, 1);
+
+        // Node which does not send the lower bound speaks a single version
+        let mut old = RawPeer::dial("proto-range-a").await;
+        let mut meta = old.meta(Capabilities::CHUNKING);
+        meta.protocol = Some(PROTOCOL_VERSION);
+        old.send(Net { meta: Some(meta), ..Default::default() }).await;
+        assert_eq!(wait_for_links(&a, 2).await, 2);
+    }).unwrap();
+}
+
+#[test]
+fn test_negotiated_chunking() {
+    actix::run(async move {
+        let a = Node::start(NodeConfig {
+            listen: vec![mem("caps-chunking-a")],
+            chunk_size: 1024,
+            ..Default::default()
+        });
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+
+        let mut plain = RawPeer::dial("caps-chunking-a").await;
+        plain.send(Net { meta: Some(plain.meta(Capabilities::default())), ..Default::default() }).await;
+        let mut chunking = RawPeer::dial("caps-chunking-a").await;
+        chunking.send(Net { meta: Some(chunking.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+        assert_eq!(wait_for_links(&a, 2).await, 2);
+
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert("blob".to_string(), "x".repeat(8 * 1024));
+        a.controller().send(UpdateMetadata { roles: vec![], metadata }).await.unwrap();
+
+        // Peer without support for chunking receives the frame whole
+        loop {
+            let frame = plain.recv().await.unwrap();
+            assert!(frame.chunk.is_none());
+            if frame.metadata.is_some() {
+                break;
+            }
+        }
+        // Peer which supports it receives it in chunks
+        loop {
+            let frame = chunking.recv().await.unwrap();
+            assert!(frame.metadata.is_none());
+            if let Some(chunk) = frame.chunk {
+                assert!(chunk.data.len() <= 1024);
+                break;
+            }
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_chunk_reassembly() {
+    actix::run(async move {
+        let config = |addr: &str| NodeConfig {
+            listen: vec![mem(addr)],
+            chunk_size: 512,
+            max_message_size: 16 * 1024,
+            ..Default::default()
+        };
+        let a = Node::start(config("chunks-a"));
+        let b = Node::start(config("chunks-b"));
+        b.controller().send(Connect { addr: mem("chunks-a") }).await.unwrap().unwrap();
+
+        // Frames are reassembled, even when more of them are sent than the remote node buffers at once
+        for i in 0..5 {
+            let mut metadata = std::collections::HashMap::new();
+            metadata.insert("blob".to_string(), i.to_string().repeat(6 * 1024));
+            a.controller().send(UpdateMetadata { roles: vec![], metadata }).await.unwrap();
+        }
+
+        let expected = "4".repeat(6 * 1024);
+        for _ in 0..100 {
+            let nodes = b.controller().send(ListNodes::default()).await.unwrap();
+            if nodes.iter().any(|n| n.metadata.get("blob") == Some(&expected)) {
+                assert_eq!(wait_for_links(&a, 1).await, 1);
+                return;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("Metadata was not reassembled");
+    }).unwrap();
+}
+
+/// Send partial frames to a node, returns whether it closed the connection
+async fn send_partial(addr: &str, frames: usize, size: usize) -> bool {
+    let mut peer = RawPeer::dial(addr).await;
+    peer.send(Net { meta: Some(peer.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+    assert!(peer.recv().await.unwrap().meta.is_some());
+
+    for id in 0..frames {
+        let chunk = Chunk { id: id as u64, data: vec![0; size], last: Some(false) };
+        if peer.framed.send(Net { chunk: Some(chunk), ..Default::default() }).await.is_err() {
+            return true;
+        }
+    }
+    loop {
+        match peer.recv_timeout(Duration::from_millis(500)).await {
+            Ok(Some(_)) => continue,
+            Ok(None) => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+#[test]
+fn test_partial_frames_limit() {
+    actix::run(async move {
+        let a = node("partial-count-a", None);
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+
+        assert!(!send_partial("partial-count-a", MAX_PARTIAL_FRAMES, 16).await);
+        assert!(send_partial("partial-count-a", MAX_PARTIAL_FRAMES + 1, 16).await);
+    }).unwrap();
+}
+
+#[test]
+fn test_partial_bytes_limit() {
+    actix::run(async move {
+        let a = Node::start(NodeConfig {
+            listen: vec![mem("partial-size-a")],
+            max_message_size: 4096,
+            ..Default::default()
+        });
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+
+        // Every frame fits, but not all of them together
+        assert!(!send_partial("partial-size-a", 2, 2000).await);
+        assert!(send_partial("partial-size-a", 3, 2000).await);
+    }).unwrap();
+}
+
+/// Shut down a node while it sends a large frame to a peer, returns whether the peer received the frame before goodbye
+async fn shutdown_while_sending(addr: &str, drain: Duration) -> bool {
+    let a = Node::start(NodeConfig {
+        listen: vec![mem(addr)],
+        chunk_size: 512,
+        ..Default::default()
+    });
+    a.controller().send(ListenAddrs).await.unwrap().unwrap();
+    let mut peer = RawPeer::dial(addr).await;
+    peer.send(Net { meta: Some(peer.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+    assert_eq!(wait_for_links(&a, 1).await, 1);
+
+    let mut metadata = std::collections::HashMap::new();
+    metadata.insert("blob".to_string(), "x".repeat(64 * 1024));
+    // Link receives the drain request before the frame is sent
+    a.controller().do_send(UpdateMetadata { roles: vec![], metadata });
+    a.controller().send(Shutdown { drain }).await.unwrap();
+
+    let mut received = false;
+    let mut last_goodbye = false;
+    while let Some(frame) = peer.recv().await {
+        if frame.chunk.map_or(false, |c| c.last == Some(true)) {
+            received = true;
+        }
+        last_goodbye = frame.goodbye.is_some();
+    }
+    received && last_goodbye
+}
+
+#[test]
+fn test_drain_sends_chunks() {
+    actix::run(async move {
+        assert!(shutdown_while_sending("drain-chunks-a", Duration::from_secs(5)).await);
+    }).unwrap();
+}
+
+#[test]
+fn test_close_flushes_chunks() {
+    actix::run(async move {
+        assert!(shutdown_while_sending("close-chunks-a", Duration::from_secs(0)).await);
+    }).unwrap();
+}
+
+#[test]
+fn test_duplicate_goodbye() {
+    actix::run(async move {
+        let a = Node::start(NodeConfig {
+            listen: vec![mem("dup-goodbye-a")],
+            heartbeat_interval: Duration::from_millis(100),
+            heartbeat_timeout: Duration::from_secs(1),
+            ..Default::default()
+        });
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+        let log = EventLog::default();
+        a.controller().send(RegisterRecipient(log.clone().start().recipient())).await.unwrap().unwrap();
+
+        let mut first = RawPeer::dial("dup-goodbye-a").await;
+        first.send(Net { meta: Some(first.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+        assert_eq!(wait_for_links(&a, 1).await, 1);
+        let recipient = log.clone().start().recipient();
+        a.controller().send(MonitorNode { node: first.id, recipient }).await.unwrap();
+
+        // Remote node keeps another link, and closes the current one before that link is established
+        let goodbye = |reason: CloseReason| Net {
+            goodbye: Some(Goodbye { reason: Some(reason as i32) }),
+            ..Default::default()
+        };
+        first.send(goodbye(CloseReason::Duplicate)).await;
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        let mut second = RawPeer::dial("dup-goodbye-a").await;
+        second.id = first.id;
+        let mut meta = second.meta(Capabilities::CHUNKING);
+        meta.link = Some(1);
+        second.send(Net { meta: Some(meta), ..Default::default() }).await;
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+
+        // Node never left
+        assert_eq!(a.controller().send(ListNodes::default()).await.unwrap().len(), 1);
+        assert_eq!(log.status.lock().unwrap().len(), 1);
+        assert!(log.down.lock().unwrap().is_empty());
+
+        // Unless the other link does not show up in time
+        second.send(goodbye(CloseReason::Duplicate)).await;
+        tokio::time::delay_for(Duration::from_millis(1500)).await;
+        assert!(a.controller().send(ListNodes::default()).await.unwrap().is_empty());
+        assert!(matches!(log.status.lock().unwrap()[1], NodeStatus::Disconnected(_, DisconnectReason::Duplicate)));
+        assert_eq!(log.down.lock().unwrap()[0].reason, DisconnectReason::Duplicate);
+    }).unwrap();
+}
+
+#[test]
+fn test_reconnect_to_new_id() {
+    actix::run(async move {
+        let a = Node::start(NodeConfig {
+            listen: vec![mem("new-id-a")],
+            reconnect: ReconnectConfig { initial: Duration::from_millis(10), ..Default::default() },
+            ..Default::default()
+        });
+        let log = EventLog::default();
+        a.controller().send(RegisterRecipient(log.clone().start().recipient())).await.unwrap().unwrap();
+        let (mut listener, _) = MemoryTransport.listen(&mem("new-id-b")).await.unwrap();
+
+        let connect = a.controller().send(Connect { addr: mem("new-id-b") });
+        let mut old = RawPeer::accept(&mut listener).await;
+        old.send(Net { meta: Some(old.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+        connect.await.unwrap().unwrap();
+
+        // Node crashes and comes back at the same address with a new id
+        let old_id = old.id;
+        drop(old);
+        let mut new = RawPeer::accept(&mut listener).await;
+        new.send(Net { meta: Some(new.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+        assert_eq!(wait_for_links(&a, 1).await, 1);
+
+        let status = log.status.lock().unwrap();
+        assert!(status.iter().any(|s| matches!(s, NodeStatus::ReconnectFailed(id) if *id == old_id)));
+        assert!(status.iter().any(|s| matches!(s, NodeStatus::Connected(id) if *id == new.id)));
+    }).unwrap();
+}
+
+#[test]
+fn test_connection_lost_fails_requests() {
+    actix::run(async move {
+        let a = node("conn-lost-a", None);
+        a.controller().send(ListenAddrs).await.unwrap().unwrap();
+        let mut peer = RawPeer::dial("conn-lost-a").await;
+        peer.send(Net { meta: Some(peer.meta(Capabilities::CHUNKING)), ..Default::default() }).await;
+        assert_eq!(wait_for_links(&a, 1).await, 1);
+
+        let remote = NodeId(peer.id);
+        let request = a.scope(async move { remote.send(Get(Key { data: b"key".to_vec() })).await });
+        // Remote node goes away while the request is in flight
+        let close = async move {
+            while peer.recv().await.map_or(false, |frame| frame.request.is_none()) {}
+        };
+
+        // Caller learns about it right away, instead of waiting for the request timeout
+        let (res, _) = futures::join!(tokio::time::timeout(Duration::from_secs(1), request), close);
+        assert!(matches!(res, Ok(Err(DispatchError::ConnectionLost))));
+    }).unwrap();
+}
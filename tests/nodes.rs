@@ -2,7 +2,6 @@ use actix::*;
 use quix::{self};
 use quix::node::{NodeConfig, NodeController, Connect};
 use actix::clock::Duration;
-use quix::global::{Global, Set};
 
 #[test]
 fn test_nodes() {
@@ -10,11 +9,10 @@ fn test_nodes() {
     env_logger::init();
     std::thread::spawn(|| {
         actix::run(async move {
-            Global::<NodeConfig>::from_registry().send(Set(NodeConfig {
-                listen: "127.0.0.1:9001".parse().unwrap(),
+            quix::start(NodeConfig {
+                listen: vec!["127.0.0.1:9001".parse().unwrap()],
                 ..Default::default()
-            })).await.unwrap();
-            NodeController::from_registry();
+            });
 
             let link = NodeController::from_registry().send(Connect {
                 addr: "127.0.0.1:9002".parse().unwrap()
@@ -25,10 +23,10 @@ fn test_nodes() {
     });
 
     actix::run(async move {
-        Global::<NodeConfig>::from_registry().send(Set(NodeConfig {
-            listen: "127.0.0.1:9002".parse().unwrap(),
+        quix::start(NodeConfig {
+            listen: vec!["127.0.0.1:9002".parse().unwrap()],
             ..Default::default()
-        })).await.unwrap();
+        });
 
         let link = NodeController::from_registry().send(Connect {
             addr: "127.0.0.1:9001".parse().unwrap()
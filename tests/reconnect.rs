@@ -0,0 +1,117 @@
+use quix::node::{Node, NodeConfig, NodeAddr, Connect, LinkError, ReconnectConfig, Transport, MemoryTransport};
+use futures::StreamExt;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+fn backoff(jitter: f64) -> ReconnectConfig {
+    ReconnectConfig {
+        initial: Duration::from_millis(100),
+        max: Duration::from_secs(1),
+        multiplier: 2.0,
+        jitter,
+        max_attempts: None,
+        connect_attempts: 5,
+    }
+}
+
+#[test]
+fn test_delay_grows_up_to_max() {
+    let cfg = backoff(0.0);
+    assert_eq!(cfg.delay(0), Duration::from_millis(100));
+    assert_eq!(cfg.delay(1), Duration::from_millis(200));
+    assert_eq!(cfg.delay(3), Duration::from_millis(800));
+    assert_eq!(cfg.delay(4), Duration::from_secs(1));
+    assert_eq!(cfg.delay(1000), Duration::from_secs(1));
+}
+
+#[test]
+fn test_delay_jitter() {
+    let cfg = backoff(0.5);
+    let delays: Vec<Duration> = (0..100).map(|_| cfg.delay(2)).collect();
+    assert!(delays.iter().all(|d| *d >= Duration::from_millis(200) && *d <= Duration::from_millis(600)));
+    assert!(delays.iter().any(|d| *d != delays[0]));
+
+    // Jitter is applied after capping the delay
+    let cfg = backoff(1.0);
+    assert!((0..100).map(|_| cfg.delay(10)).all(|d| d <= Duration::from_secs(2)));
+}
+
+#[test]
+fn test_max_attempts() {
+    let cfg = ReconnectConfig { max_attempts: Some(3), ..backoff(0.0) };
+    assert!(!cfg.gives_up(2));
+    assert!(cfg.gives_up(3));
+    assert!(!backoff(0.0).gives_up(u32::MAX));
+
+    actix::run(async move {
+        let node = Node::start(NodeConfig {
+            listen: vec![NodeAddr::Memory("reconnect-0".to_string())],
+            reconnect: ReconnectConfig {
+                initial: Duration::from_millis(1),
+                ..cfg
+            },
+            ..Default::default()
+        });
+        let res = node.controller().send(Connect { addr: NodeAddr::Memory("reconnect-nowhere".to_string()) }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Io(_))));
+    }).unwrap();
+}
+
+/// Listen on `name` like a node which never completes the handshake.
+///
+/// Connections are held open if `hold` is set, and closed right away otherwise. Returns number of accepted connections
+async fn stalled_node(name: &str, hold: bool) -> Arc<AtomicUsize> {
+    let addr = NodeAddr::Memory(name.to_string());
+    let (mut listener, _) = MemoryTransport.listen(&addr).await.unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let count = accepted.clone();
+    actix::spawn(async move {
+        let mut held = vec![];
+        while let Some(Ok((conn, _))) = listener.next().await {
+            count.fetch_add(1, Ordering::SeqCst);
+            if hold {
+                held.push(conn);
+            }
+        }
+    });
+    accepted
+}
+
+fn dialer(name: &str) -> Node {
+    Node::start(NodeConfig {
+        listen: vec![NodeAddr::Memory(name.to_string())],
+        handshake_timeout: Duration::from_millis(100),
+        reconnect: ReconnectConfig {
+            initial: Duration::from_millis(1),
+            max_attempts: Some(3),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_handshake_timeout_is_retried() {
+    actix::run(async move {
+        let accepted = stalled_node("reconnect-stalled", true).await;
+        let node = dialer("reconnect-1");
+
+        let res = node.controller().send(Connect { addr: NodeAddr::Memory("reconnect-stalled".to_string()) }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut));
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }).unwrap();
+}
+
+#[test]
+fn test_closed_handshake_is_retried() {
+    actix::run(async move {
+        let accepted = stalled_node("reconnect-closing", false).await;
+        let node = dialer("reconnect-2");
+
+        let res = node.controller().send(Connect { addr: NodeAddr::Memory("reconnect-closing".to_string()) }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }).unwrap();
+}
@@ -0,0 +1,374 @@
+#![cfg(feature = "sim")]
+
+mod common;
+
+use actix::{Actor, Handler, Message};
+use bytes::{Buf, BufMut};
+use quix::{Pid, Process};
+use quix::node::{GetLinkStats, ListNodes, NodeConfig, ReconnectConfig, NodeId, NodeStatus, DisconnectReason, Shutdown};
+use quix::process::DispatchError;
+use quix::memkv::Write;
+use quix::proto::{Get, Key};
+use quix::sim::Sim;
+use quix::util::{RegisterRecipient, RpcMethod};
+use std::time::Duration;
+use common::EventLog;
+
+#[derive(Clone, prost::Message)]
+pub struct Ping {}
+
+impl Message for Ping {
+    type Result = ();
+}
+
+impl RpcMethod for Ping {
+    const NAME: &'static str = "sim.Ping";
+    const ID: u32 = 7;
+
+    fn read(b: impl Buf) -> Result<Self, DispatchError> {
+        Ok(prost::Message::decode(b)?)
+    }
+
+    fn write(&self, b: &mut impl BufMut) -> Result<(), DispatchError> {
+        Ok(prost::Message::encode(self, b)?)
+    }
+
+    fn read_result(_: impl Buf) -> Self::Result {}
+
+    fn write_result(_: &Self::Result, _: &mut impl BufMut) -> Result<(), DispatchError> {
+        Ok(())
+    }
+}
+
+#[derive(quix::DynHandler)]
+#[dispatch(Ping)]
+pub struct Pong;
+
+impl Actor for Pong {
+    type Context = Process<Self>;
+}
+
+impl Handler<Ping> for Pong {
+    type Result = ();
+
+    fn handle(&mut self, _: Ping, _: &mut Process<Self>) {}
+}
+
+#[derive(Clone, prost::Message)]
+pub struct Blob {
+    #[prost(bytes, tag = "1")]
+    pub data: Vec<u8>,
+}
+
+impl Message for Blob {
+    type Result = ();
+}
+
+impl RpcMethod for Blob {
+    const NAME: &'static str = "sim.Blob";
+    const ID: u32 = 8;
+
+    fn read(b: impl Buf) -> Result<Self, DispatchError> {
+        Ok(prost::Message::decode(b)?)
+    }
+
+    fn write(&self, b: &mut impl BufMut) -> Result<(), DispatchError> {
+        Ok(prost::Message::encode(self, b)?)
+    }
+
+    fn read_result(_: impl Buf) -> Self::Result {}
+
+    fn write_result(_: &Self::Result, _: &mut impl BufMut) -> Result<(), DispatchError> {
+        Ok(())
+    }
+}
+
+#[derive(quix::DynHandler)]
+#[dispatch(Blob)]
+pub struct Sink;
+
+impl Actor for Sink {
+    type Context = Process<Self>;
+}
+
+impl Handler<Blob> for Sink {
+    type Result = ();
+
+    fn handle(&mut self, _: Blob, _: &mut Process<Self>) {}
+}
+
+#[test]
+fn test_process_table_converges() {
+    actix::run(async move {
+        let sim = Sim::start(3, 1).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+
+        let res = sim.run(2, async { remote.send(Ping {}).await }).await;
+        assert!(matches!(res, Err(DispatchError::ProcessNotFound)));
+
+        sim.advance(Duration::from_secs(2)).await;
+        for i in 1..3 {
+            assert!(sim.run(i, async { remote.send(Ping {}).await }).await.is_ok());
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_partition_and_heal() {
+    actix::run(async move {
+        let sim = Sim::start(3, 2).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+        sim.advance(Duration::from_secs(2)).await;
+
+        sim.partition(0, 2);
+        sim.advance(Duration::from_secs(7)).await;
+
+        let peers = sim.node(2).controller().send(ListNodes::default()).await.unwrap();
+        assert!(!peers.iter().any(|n| n.id == sim.id(0)));
+        assert!(peers.iter().any(|n| n.id == sim.id(1)));
+
+        let res = sim.run(2, async { remote.send(Ping {}).await }).await;
+        assert!(matches!(res, Err(DispatchError::NodeNotFound)));
+        // Unaffected nodes keep working
+        assert!(sim.run(1, async { remote.send(Ping {}).await }).await.is_ok());
+
+        sim.heal(0, 2);
+        sim.advance(Duration::from_secs(10)).await;
+
+        let peers = sim.node(2).controller().send(ListNodes::default()).await.unwrap();
+        assert!(peers.iter().any(|n| n.id == sim.id(0)));
+        assert!(sim.run(2, async { remote.send(Ping {}).await }).await.is_ok());
+    }).unwrap();
+}
+
+#[test]
+fn test_dropped_requests_time_out() {
+    actix::run(async move {
+        let sim = Sim::start(2, 3).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+        sim.advance(Duration::from_secs(2)).await;
+
+        sim.set_drop(1, 0, 1.0);
+        let res = sim.run(1, async {
+            remote.send(Ping {}).timeout(Duration::from_secs(1)).await
+        }).await;
+        assert!(matches!(res, Err(DispatchError::Timeout)));
+
+        // Link forgets about the request
+        let stats = sim.node(1).controller().send(GetLinkStats).await.unwrap();
+        assert_eq!(stats[&sim.id(0)].pending, 0);
+    }).unwrap();
+}
+
+#[test]
+fn test_latency() {
+    actix::run(async move {
+        let sim = Sim::start(2, 4).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+        sim.advance(Duration::from_secs(2)).await;
+
+        sim.set_latency(1, 0, Duration::from_millis(300));
+        sim.set_latency(0, 1, Duration::from_millis(300));
+
+        let start = tokio::time::Instant::now();
+        assert!(sim.run(1, async { remote.send(Ping {}).await }).await.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(600));
+    }).unwrap();
+}
+
+#[test]
+fn test_link_stats() {
+    actix::run(async move {
+        let sim = Sim::start_with(2, 11, |_, cfg| NodeConfig {
+            heartbeat_interval: Duration::from_millis(10),
+            ..cfg
+        }).await;
+        sim.set_latency(0, 1, Duration::from_millis(100));
+        sim.set_latency(1, 0, Duration::from_millis(100));
+        // Enough heartbeats to push samples from before the latency out of the window
+        sim.advance(Duration::from_secs(5)).await;
+
+        let stats = sim.node(1).controller().send(GetLinkStats).await.unwrap();
+        let rtt = stats[&sim.id(0)].rtt.clone().unwrap();
+        assert!(rtt.samples > 0);
+        assert!(rtt.min >= Duration::from_millis(200), "{:?}", rtt);
+        assert!(rtt.p99 >= rtt.min && rtt.p99 < Duration::from_millis(300), "{:?}", rtt);
+
+        // Once frames in flight arrived, both ends counted the same traffic
+        sim.set_latency(0, 1, Duration::from_millis(0));
+        sim.set_latency(1, 0, Duration::from_millis(0));
+        sim.advance(Duration::from_secs(1)).await;
+        let a = &sim.node(0).controller().send(GetLinkStats).await.unwrap()[&sim.id(1)];
+        let b = &sim.node(1).controller().send(GetLinkStats).await.unwrap()[&sim.id(0)];
+        assert!(a.frames_sent > 0 && a.bytes_sent > 0 && b.frames_sent > 0 && b.bytes_sent > 0);
+        assert_eq!(a.frames_sent, b.frames_received);
+        assert_eq!(a.bytes_sent, b.bytes_received);
+        assert_eq!(b.frames_sent, a.frames_received);
+        assert_eq!(b.bytes_sent, a.bytes_received);
+    }).unwrap();
+}
+
+#[test]
+fn test_monitor_node() {
+    actix::run(async move {
+        let sim = Sim::start(3, 5).await;
+        let log = EventLog::default();
+        let watcher = log.clone().start().recipient();
+
+        let unknown = uuid::Uuid::new_v4();
+        sim.run(0, NodeId(unknown).monitor(watcher.clone())).await.unwrap();
+        let monitor1 = sim.run(0, NodeId(sim.id(1)).monitor(watcher.clone())).await.unwrap();
+        sim.run(0, NodeId(sim.id(2)).monitor(watcher.clone())).await.unwrap();
+        sim.node(0).scope(async { NodeId::demonitor(monitor1) }).await;
+
+        sim.isolate(0);
+        sim.advance(Duration::from_secs(7)).await;
+
+        let events = log.down.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].node, unknown);
+        assert_eq!(events[0].reason, DisconnectReason::NotLinked);
+        assert_eq!(events[1].node, sim.id(2));
+        assert_eq!(events[1].reason, DisconnectReason::Unreachable);
+    }).unwrap();
+}
+
+#[test]
+fn test_shutdown() {
+    actix::run(async move {
+        let sim = Sim::start(3, 6).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+        sim.advance(Duration::from_secs(2)).await;
+
+        let shutdown = sim.node(0).controller().send(Shutdown { drain: Duration::from_secs(1) });
+        sim.run(0, shutdown).await.unwrap();
+
+        // Peers forget processes of the node right away, instead of timing out
+        for i in 1..3 {
+            let res = sim.run(i, async { remote.send(Ping {}).await }).await;
+            assert!(matches!(res, Err(DispatchError::ProcessNotFound)));
+        }
+
+        // and don't try to reconnect
+        sim.advance(Duration::from_secs(10)).await;
+        for i in 1..3 {
+            let peers = sim.node(i).controller().send(ListNodes::default()).await.unwrap();
+            assert_eq!(peers.len(), 1);
+        }
+    }).unwrap();
+}
+
+#[test]
+fn test_shutdown_while_reconnecting() {
+    actix::run(async move {
+        let sim = Sim::start(3, 8).await;
+
+        // Node 2 keeps dialing node 0 while they are partitioned
+        sim.partition(0, 2);
+        sim.advance(Duration::from_secs(10)).await;
+        let shutdown = sim.node(2).controller().send(Shutdown { drain: Duration::from_secs(1) });
+        sim.run(2, shutdown).await.unwrap();
+
+        // and stops once it leaves the cluster
+        sim.heal(0, 2);
+        sim.advance(Duration::from_secs(60)).await;
+        for i in 0..2 {
+            let peers = sim.node(i).controller().send(ListNodes::default()).await.unwrap();
+            assert!(peers.iter().all(|n| n.id != sim.id(2)));
+        }
+        assert!(sim.node(2).controller().send(ListNodes::default()).await.unwrap().is_empty());
+    }).unwrap();
+}
+
+#[test]
+fn test_reconnect() {
+    actix::run(async move {
+        let sim = Sim::start(3, 7).await;
+        let log = EventLog::default();
+        sim.node(2).controller().send(RegisterRecipient(log.clone().start().recipient())).await.unwrap().unwrap();
+
+        // Node 2 joined through node 0, and keeps dialing it once the link drops
+        sim.partition(0, 2);
+        sim.advance(Duration::from_secs(10)).await;
+        sim.heal(0, 2);
+        sim.advance(Duration::from_secs(10)).await;
+
+        let peers = sim.node(2).controller().send(ListNodes::default()).await.unwrap();
+        assert!(peers.iter().any(|n| n.id == sim.id(0)));
+
+        let events = log.status.lock().unwrap();
+        let id = sim.id(0);
+        assert!(matches!(events[0], NodeStatus::Disconnected(node, DisconnectReason::Unreachable) if node == id));
+        let retries = events[1..].iter()
+            .take_while(|e| matches!(e, NodeStatus::Reconnecting(node, _) if *node == id))
+            .enumerate()
+            .all(|(i, e)| matches!(e, NodeStatus::Reconnecting(_, attempt) if *attempt == i as u32 + 1));
+        assert!(retries);
+        assert!(matches!(events[1], NodeStatus::Reconnecting(..)));
+        assert!(events.iter().any(|e| matches!(e, NodeStatus::Connected(node) if *node == id)));
+    }).unwrap();
+}
+
+#[test]
+fn test_forget_processes_of_lost_nodes() {
+    actix::run(async move {
+        let sim = Sim::start_with(2, 10, |_, cfg| NodeConfig {
+            reconnect: ReconnectConfig { initial: Duration::from_secs(2), max_attempts: Some(2), ..cfg.reconnect },
+            ..cfg
+        }).await;
+        let pid = Process::start_in(sim.node(0), Pong);
+        let remote = Pid::<Pong>::from(pid.id());
+        sim.advance(Duration::from_secs(2)).await;
+
+        // Processes are kept while node 1 tries to get back to node 0
+        sim.partition(0, 1);
+        sim.advance(Duration::from_secs(7)).await;
+        let res = sim.run(1, async { remote.send(Ping {}).await }).await;
+        assert!(matches!(res, Err(DispatchError::NodeNotFound)));
+
+        // and forgotten once it gives up
+        sim.advance(Duration::from_secs(10)).await;
+        let res = sim.run(1, async { remote.send(Ping {}).await }).await;
+        assert!(matches!(res, Err(DispatchError::ProcessNotFound)));
+    }).unwrap();
+}
+
+#[test]
+fn test_oversized_messages() {
+    actix::run(async move {
+        let sim = Sim::start_with(2, 9, |_, cfg| NodeConfig {
+            chunk_size: 1024,
+            max_message_size: 16 * 1024,
+            ..cfg
+        }).await;
+        let pid = Process::start_in(sim.node(0), Sink);
+        let remote = Pid::<Sink>::from(pid.id());
+        let key = b"large".to_vec();
+        sim.node(0).memkv().send(Write { key: key.clone(), value: vec![1; 32 * 1024] }).await.unwrap();
+        sim.advance(Duration::from_secs(2)).await;
+
+        let send = |size: usize| {
+            let remote = remote.clone();
+            sim.run(1, async move { remote.send(Blob { data: vec![1; size] }).await })
+        };
+        // Large messages within the limit are sent in chunks
+        assert!(send(8 * 1024).await.is_ok());
+
+        // Only the oversized request and response fail
+        let res = send(32 * 1024).await;
+        assert!(matches!(res, Err(DispatchError::MessageTooLarge)));
+        let node = NodeId(sim.id(0));
+        let res = sim.run(1, async move { node.send(Get(Key { data: key })).await }).await;
+        assert!(matches!(res, Err(DispatchError::MessageTooLarge)));
+
+        // while the link stays up
+        assert!(send(10).await.is_ok());
+        let peers = sim.node(1).controller().send(ListNodes::default()).await.unwrap();
+        assert_eq!(peers.len(), 1);
+    }).unwrap();
+}
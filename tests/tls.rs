@@ -0,0 +1,81 @@
+use quix::node::{Node, NodeConfig, NodeAddr, Connect, ListNodes, ListenAddrs, LinkError, TlsConfig, Transport, MemoryTransport};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Certificates made by `tests/certs/generate.sh`
+fn cert_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/certs").join(name)
+}
+
+fn mem(name: &str) -> NodeAddr {
+    NodeAddr::Memory(name.to_string())
+}
+
+/// Node with id `id`, presenting certificate `cert`
+fn tls_node(addr: &str, id: u128, cert: &str, verify_node_id: bool) -> Node {
+    Node::start(NodeConfig {
+        id: Uuid::from_u128(id),
+        listen: vec![mem(addr)],
+        tls: Some(TlsConfig {
+            cert: cert_file(&format!("{}.pem", cert)),
+            key: cert_file(&format!("{}.key", cert)),
+            ca: cert_file("ca.pem"),
+            verify_node_id,
+        }),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_mutual_tls() {
+    actix::run(async move {
+        let a = tls_node("tls-a", 0xa, "node-a", true);
+        let b = tls_node("tls-b", 0xb, "node-b", true);
+
+        b.controller().send(Connect { addr: mem("tls-a") }).await.unwrap().unwrap();
+        let peers = a.controller().send(ListNodes::default()).await.unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, Uuid::from_u128(0xb));
+    }).unwrap();
+}
+
+#[test]
+fn test_node_id_mismatch() {
+    actix::run(async move {
+        let a = tls_node("tls-verify", 0xa, "node-a", true);
+        let trusting = tls_node("tls-trusting", 0xd, "node-a", false);
+        // Certificate is signed by the trusted CA, but issued for another node
+        let _c = tls_node("tls-impostor", 0xc, "node-b", false);
+
+        let res = a.controller().send(Connect { addr: mem("tls-impostor") }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Identity(id)) if id == Uuid::from_u128(0xc)));
+
+        // Without verification of node ids, any node with a trusted certificate is accepted
+        trusting.controller().send(Connect { addr: mem("tls-impostor") }).await.unwrap().unwrap();
+    }).unwrap();
+}
+
+#[test]
+fn test_invalid_tls_config() {
+    actix::run(async move {
+        let node = Node::start(NodeConfig {
+            listen: vec![mem("tls-invalid")],
+            tls: Some(TlsConfig {
+                cert: cert_file("missing.pem"),
+                key: cert_file("node-a.key"),
+                ca: cert_file("ca.pem"),
+                verify_node_id: false,
+            }),
+            ..Default::default()
+        });
+
+        let err = node.controller().send(ListenAddrs).await.unwrap().unwrap_err();
+        assert_eq!(err.addr, None);
+        assert_eq!(err.kind, std::io::ErrorKind::NotFound);
+
+        // Connections are rejected right away, instead of being retried
+        let (_listener, _) = MemoryTransport.listen(&mem("tls-invalid-peer")).await.unwrap();
+        let res = node.controller().send(Connect { addr: mem("tls-invalid-peer") }).await.unwrap();
+        assert!(matches!(res, Err(LinkError::Tls(_))));
+    }).unwrap();
+}
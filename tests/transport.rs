@@ -0,0 +1,118 @@
+use quix::node::{NodeAddr, MemoryStream};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use futures::StreamExt;
+use std::path::PathBuf;
+
+#[test]
+fn test_parse_addr() {
+    assert_eq!("127.0.0.1:9001".parse(), Ok(NodeAddr::Tcp(([127, 0, 0, 1], 9001).into())));
+    assert_eq!("tcp://127.0.0.1:9001".parse(), Ok(NodeAddr::Tcp(([127, 0, 0, 1], 9001).into())));
+    assert_eq!("unix:///tmp/quix.sock".parse(), Ok(NodeAddr::Unix(PathBuf::from("/tmp/quix.sock"))));
+    assert_eq!("mem://node1".parse(), Ok(NodeAddr::Memory("node1".to_string())));
+
+    assert!("mem://".parse::<NodeAddr>().is_err());
+    assert!("localhost".parse::<NodeAddr>().is_err());
+
+    let addr = NodeAddr::Memory("node1".to_string());
+    assert_eq!(addr.to_string().parse(), Ok(addr));
+}
+
+#[test]
+fn test_unspecified_addr_seen_at() {
+    let seen: NodeAddr = "192.168.1.7:41234".parse().unwrap();
+    let any: NodeAddr = "0.0.0.0:9001".parse().unwrap();
+    assert_eq!(any.seen_at(&seen), "192.168.1.7:9001".parse().unwrap());
+    let any: NodeAddr = "[::]:9001".parse().unwrap();
+    assert_eq!(any.seen_at(&seen), "192.168.1.7:9001".parse().unwrap());
+
+    // Specific addresses and other transports are kept
+    let specific: NodeAddr = "10.0.0.1:9001".parse().unwrap();
+    assert_eq!(specific.seen_at(&seen), specific);
+    let mem = NodeAddr::Memory("node1".to_string());
+    assert_eq!(mem.seen_at(&seen), mem);
+}
+
+#[test]
+fn test_memory_stream() {
+    actix::run(async move {
+        let (mut a, mut b) = MemoryStream::pair();
+        a.write_all(b"hello").await.unwrap();
+
+        let mut buf = [0; 5];
+        b.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        a.shutdown().await.unwrap();
+        assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+    }).unwrap();
+}
+
+#[test]
+fn test_memory_transport() {
+    actix::run(async move {
+        let addr = NodeAddr::Memory("test_memory_transport".to_string());
+        let (mut listener, bound) = addr.transport().listen(&addr).await.unwrap();
+        assert_eq!(bound, addr);
+        assert!(addr.transport().listen(&addr).await.is_err());
+
+        let mut client = addr.transport().dial(&addr).await.unwrap();
+        let (mut server, _) = listener.next().await.unwrap().unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        drop(listener);
+        assert!(addr.transport().dial(&addr).await.is_err());
+    }).unwrap();
+}
+
+#[test]
+fn test_tcp_ephemeral_port() {
+    actix::run(async move {
+        let addr: NodeAddr = "127.0.0.1:0".parse().unwrap();
+        let (mut listener, bound) = addr.transport().listen(&addr).await.unwrap();
+        match bound {
+            NodeAddr::Tcp(bound) => assert_ne!(bound.port(), 0),
+            other => panic!("Unexpected address: {}", other),
+        }
+
+        let mut client = bound.transport().dial(&bound).await.unwrap();
+        let (mut server, _) = listener.next().await.unwrap().unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_transport() {
+    actix::run(async move {
+        let path = std::env::temp_dir().join(format!("quix-test-{}.sock", std::process::id()));
+        // Leftover of a node which didn't stop cleanly
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let addr = NodeAddr::Unix(path.clone());
+        let (mut listener, bound) = addr.transport().listen(&addr).await.unwrap();
+        assert_eq!(bound, addr);
+
+        let mut client = addr.transport().dial(&addr).await.unwrap();
+        let (mut server, _) = listener.next().await.unwrap().unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Socket of a running listener is not taken over
+        assert!(addr.transport().listen(&addr).await.is_err());
+        drop(listener);
+        assert!(!path.exists());
+        assert!(addr.transport().dial(&addr).await.is_err());
+    }).unwrap();
+}